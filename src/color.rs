@@ -3,6 +3,9 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::ops::{Index, IndexMut};
 
+// lakes are shaded by depth like the sea, in colors of their own
+pub const LAKE_SHADES: u16 = 8;
+
 #[derive(Clone, Debug)]
 pub struct ColorTable {
     rows: Vec<Color>,
@@ -18,6 +21,8 @@ pub struct ColorTable {
     pub highest_land: u16,
     pub sea_depth: u16,
     pub land_height: u16,
+    pub river: u16,
+    pub lake: u16,
 }
 
 impl ColorTable {
@@ -35,33 +40,59 @@ impl ColorTable {
             lowest_land: 8,
             highest_land: 9,
             sea_depth: 0,
-            land_height: 0
+            land_height: 0,
+            river: 7,
+            lake: 7,
         }
     }
 
-    /// Returns true if the table contains only black and white
-    ///
-    /// Reasonably this _could_ be written to be true if it's two distinct colors
-    /// But that would be a feature upgrade
+    /// Returns true if the table contains no more than two distinct colors
     pub fn is_monochrome(&self) -> bool {
+        self.get_monochrome_colors().is_some()
+    }
+
+    /// Returns the two colors of a monochrome table, darkest first
+    ///
+    /// A table with a single color returns that color twice
+    pub fn get_monochrome_colors(&self) -> Option<(Color, Color)> {
+        let first = self.rows.first()?;
+        let mut second: Option<&Color> = None;
         for row in &self.rows {
-            match row {
-                Color {
-                    red: 0,
-                    green: 0,
-                    blue: 0,
-                } => {}
-                Color {
-                    red: 255,
-                    green: 255,
-                    blue: 255,
-                } => {}
-                _ => {
-                    return false;
-                }
+            if row == first {
+                continue;
+            }
+            match second {
+                None => second = Some(row),
+                Some(color) if color != row => return None,
+                _ => {}
             }
         }
-        true
+        let second = second.unwrap_or(first);
+        if first.luminance() <= second.luminance() {
+            Some((first.clone(), second.clone()))
+        } else {
+            Some((second.clone(), first.clone()))
+        }
+    }
+
+    /// Adds a color to the end of the table, returning its index
+    pub fn push(&mut self, color: Color) -> u16 {
+        self.rows.push(color);
+        (self.rows.len() - 1) as u16
+    }
+
+    /// Gives rivers the color of the shallowest sea, under their own index
+    pub fn add_river_color(&mut self) {
+        self.river = self.push(self[self.sea_level as usize].clone());
+    }
+
+    /// Gives lakes copies of the sea colors from the shallowest to the deepest, under their own indices
+    pub fn add_lake_colors(&mut self) {
+        self.lake = self.len() as u16;
+        for i in 0..LAKE_SHADES {
+            let depth = (self.sea_depth as u32 * i as u32 / (LAKE_SHADES - 1) as u32) as u16;
+            self.push(self[(self.sea_level - depth) as usize].clone());
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -83,7 +114,7 @@ impl IndexMut<usize> for ColorTable {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -102,6 +133,26 @@ impl Color {
             blue: b,
         }
     }
+
+    /// Perceived brightness, from 0 to 255
+    pub fn luminance(&self) -> f64 {
+        0.299 * self.red as f64 + 0.587 * self.green as f64 + 0.114 * self.blue as f64
+    }
+}
+
+#[test]
+fn test_monochrome_detection() {
+    let mut table = ColorTable::new(10);
+    assert!(table.is_monochrome());
+
+    table[3] = Color::from_colors(20, 80, 40);
+    table[7] = Color::from_colors(20, 80, 40);
+    let (dark, light) = table.get_monochrome_colors().unwrap();
+    assert_eq!(dark, Color::new());
+    assert_eq!(light, Color::from_colors(20, 80, 40));
+
+    table[8] = Color::from_colors(255, 255, 255);
+    assert!(!table.is_monochrome());
 }
 
 #[derive(Clone, Debug)]
@@ -119,35 +170,17 @@ impl ColorRow {
     }
 }
 
-const T: usize = 'T' as usize - 64;
-const G: usize = 'G' as usize - 64;
-const B: usize = 'B' as usize - 64;
-const D: usize = 'D' as usize - 64;
-const S: usize = 'S' as usize - 64;
-const F: usize = 'F' as usize - 64;
-const R: usize = 'R' as usize - 64;
-const W: usize = 'W' as usize - 64;
-const E: usize = 'E' as usize - 64;
-const O: usize = 'O' as usize - 64;
-const I: usize = 'I' as usize - 64;
-
 // TODO: use include_str!() to embed color data into the program in build script
-pub fn build_color_data(color_filename: &str, show_biomes: bool) -> ColorTable {
+/// Land colors replace the colors above the lowest land, growing the table if it is too short
+pub fn build_color_data(color_filename: &str, land_colors: &[(usize, Color)]) -> ColorTable {
     let mut table = generate_color_data(color_filename);
 
-    if show_biomes {
-        let lowest_land = table.lowest_land as usize;
-        table[T + lowest_land] = Color::from_colors(210, 210, 210);
-        table[G + lowest_land] = Color::from_colors(250, 215, 165);
-        table[B + lowest_land] = Color::from_colors(105, 155, 120);
-        table[D + lowest_land] = Color::from_colors(220, 195, 175);
-        table[S + lowest_land] = Color::from_colors(225, 155, 100);
-        table[F + lowest_land] = Color::from_colors(155, 215, 170);
-        table[R + lowest_land] = Color::from_colors(170, 195, 200);
-        table[W + lowest_land] = Color::from_colors(185, 150, 160);
-        table[E + lowest_land] = Color::from_colors(130, 190, 25);
-        table[O + lowest_land] = Color::from_colors(110, 160, 170);
-        table[I + lowest_land] = Color::from_colors(255, 255, 255);
+    let lowest_land = table.lowest_land as usize;
+    for (offset, color) in land_colors {
+        if lowest_land + offset >= table.len() {
+            table.rows.resize(lowest_land + offset + 1, Color::new());
+        }
+        table[lowest_land + offset] = color.clone();
     }
 
     table
@@ -247,6 +280,8 @@ fn generate_color_data(filename: &str) -> ColorTable {
     table.highest_land = highest_land as u16;
     table.sea_level = sea_level as u16;
     table.lowest_land = lowest_land as u16;
+    table.river = sea_level as u16;
+    table.lake = sea_level as u16;
 
     table.sea_depth = table.sea_level - table.sea_bottom;
     table.land_height = table.highest_land - table.lowest_land;
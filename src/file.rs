@@ -1,10 +1,22 @@
+pub mod biomes;
 pub mod bitmap;
+mod font;
+mod healpix;
 mod heightfield;
+mod landmasses;
 pub mod png;
 pub mod ppm;
+mod rivers;
+pub mod stats;
 pub mod xpm;
 
+use crate::color::Color;
+use crate::parse_commandline_footer;
+use crate::render::altitude::get_healpix_altitudes;
+use crate::render::landmass::find_landmasses;
+use crate::render::stats::{get_planet_stats, StatsFormat};
 use crate::render::RenderState;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::{BufWriter, Write};
@@ -18,6 +30,7 @@ pub enum FileType {
     xpm = 3,
     heightfield = 4,
     png = 5,
+    normalmap = 6,
 }
 
 pub enum ColorMode {
@@ -25,13 +38,17 @@ pub enum ColorMode {
     Monochrome,
 }
 
-pub fn get_file_extension<'a>(filetype: &FileType) -> &'a str {
+pub fn get_file_extension<'a>(filetype: &FileType, color_mode: &ColorMode) -> &'a str {
     match filetype {
         FileType::bmp => ".bmp",
-        FileType::ppm => ".ppm",
+        FileType::ppm => match color_mode {
+            ColorMode::Color => ".ppm",
+            ColorMode::Monochrome => ".pbm",
+        },
         FileType::xpm => ".xpm",
         FileType::heightfield => ".heightfield",
         FileType::png => ".png",
+        FileType::normalmap => ".normal.png",
     }
 }
 
@@ -39,12 +56,73 @@ pub fn write_file(state: Arc<RenderState>) -> Result<(), io::Error> {
     if let Some(filename) = state.options.output_file.clone() {
         for filetype in &state.options.filetypes {
             let mut filename = filename.to_owned();
-            filename.push_str(get_file_extension(filetype));
+            filename.push_str(get_file_extension(filetype, &state.get_color_mode()));
             let file = File::create(&filename)?;
             write_to(state.clone(), filetype, &mut BufWriter::new(file))?;
         }
+        if state.options.write_biome_sidecar {
+            let file = File::create(format!("{filename}.biomes.json"))?;
+            biomes::write_sidecar(state.clone(), &mut BufWriter::new(file))?;
+        }
+        if state.options.write_biome_legend {
+            let file = File::create(format!("{filename}.legend.png"))?;
+            biomes::write_legend(state.clone(), &mut BufWriter::new(file))?;
+        }
+        if let Some(format) = state.options.stats_format {
+            let stats = get_planet_stats(&state.options);
+            if let StatsFormat::Json = format {
+                let file = File::create(format!("{filename}.stats.json"))?;
+                stats::write_json(&stats, &mut BufWriter::new(file))?;
+            } else {
+                let file = File::create(format!("{filename}.stats.txt"))?;
+                stats::write_text(&stats, &mut BufWriter::new(file))?;
+            }
+        }
+        if state.options.find_landmasses {
+            let (labels, landmasses) = find_landmasses(&state);
+            println!("Found {} landmasses", landmasses.len());
+            if state.options.write_landmass_report {
+                let file = File::create(format!("{filename}.landmasses.json"))?;
+                landmasses::write_report(&landmasses, &mut BufWriter::new(file))?;
+            }
+            if state.options.write_landmass_labels {
+                let file = File::create(format!("{filename}.landmasses.png"))?;
+                png::write_labels(&labels, &mut BufWriter::new(file))?;
+            }
+        }
+        if state.options.draw_rivers {
+            let file = File::create(format!("{filename}.rivers.geojson"))?;
+            rivers::write_geojson(&state.rivers.read().unwrap(), &mut BufWriter::new(file))?;
+        }
+        if let Some(nside) = state.options.healpix_nside {
+            let altitudes = get_healpix_altitudes(&state.options, nside);
+            println!(
+                "Writing {} HEALPix pixels in {} order",
+                altitudes.len(),
+                state.options.healpix_order.get_name()
+            );
+            let file = File::create(format!("{filename}.healpix"))?;
+            healpix::write_raw(&altitudes, &mut BufWriter::new(file))?;
+        }
+        for (i, layer) in state.options.layers.iter().enumerate() {
+            let filename = format!("{filename}.{}.png", layer.get_name());
+            let file = File::create(&filename)?;
+            png::write_layer(state.clone(), i, &mut BufWriter::new(file))?;
+        }
         Ok(())
     } else {
+        if !state.options.layers.is_empty()
+            || state.options.write_biome_sidecar
+            || state.options.write_biome_legend
+            || state.options.stats_format.is_some()
+            || state.options.find_landmasses
+            || state.options.draw_rivers
+            || state.options.healpix_nside.is_some()
+        {
+            eprintln!(
+                "Layers, statistics, landmasses, rivers, HEALPix grids and biome information are only written when an output file is given"
+            );
+        }
         write_to(
             state.clone(),
             &state.options.filetypes[0],
@@ -53,6 +131,15 @@ pub fn write_file(state: Arc<RenderState>) -> Result<(), io::Error> {
     }
 }
 
+/// Maps each color table index to its bit in 1-bit output, where the lighter color is set
+fn get_monochrome_bits(state: &RenderState) -> Vec<bool> {
+    let color_table = &state.options.color_table;
+    let (_, light) = color_table
+        .get_monochrome_colors()
+        .unwrap_or((Color::new(), Color::new()));
+    (0..color_table.len()).map(|i| color_table[i] == light).collect()
+}
+
 fn write_to<W: Write>(
     state: Arc<RenderState>,
     filetype: &FileType,
@@ -64,5 +151,45 @@ fn write_to<W: Write>(
         FileType::ppm => ppm::write_to(state.clone(), writer),
         FileType::xpm => xpm::write_to(state.clone(), writer),
         FileType::png => png::write_to(state.clone(), writer),
+        FileType::normalmap => png::write_normal_map(state.clone(), writer),
+    }
+}
+
+/// Reads back the command line embedded in an image written by this program
+pub fn read_commandline(filename: &str) -> Result<Vec<String>, io::Error> {
+    let data = fs::read(filename)?;
+    let commandline = if data.starts_with(png::SIGNATURE) {
+        png::read_commandline(&data)?
+    } else {
+        // BMP and PPM files carry a trailing footer, and XPM files carry it in a comment
+        parse_commandline_footer(&String::from_utf8_lossy(&data))
+    };
+    commandline.ok_or(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "no command line was found in the file",
+    ))
+}
+
+/// Quotes text as a JSON string, escaping the characters JSON doesn't allow in one
+fn get_json_string(text: &str) -> String {
+    let mut retval = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => retval.push_str("\\\""),
+            '\\' => retval.push_str("\\\\"),
+            '\n' => retval.push_str("\\n"),
+            '\r' => retval.push_str("\\r"),
+            '\t' => retval.push_str("\\t"),
+            c if c.is_control() => retval.push_str(&format!("\\u{:04x}", c as u32)),
+            c => retval.push(c),
+        }
     }
+    retval.push('"');
+    retval
+}
+
+#[test]
+fn test_json_string() {
+    assert_eq!(get_json_string("Tundra"), "\"Tundra\"");
+    assert_eq!(get_json_string("a \"b\" \\ c\n\u{1}"), "\"a \\\"b\\\" \\\\ c\\n\\u0001\"");
 }
@@ -0,0 +1,119 @@
+use crate::file::font::{draw_text, get_text_width, GLYPH_HEIGHT};
+use crate::file::get_json_string;
+use crate::file::png;
+use crate::render::RenderState;
+use crate::terrain::koppen::KOPPEN_ZONES;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+
+struct BiomeCoverage {
+    code: Option<String>,
+    name: String,
+    color: [u8; 3],
+    pixels: u64,
+    percentage: f64,
+}
+
+/// Water first, then each biome in table order or each Köppen zone
+fn get_coverage(state: &RenderState) -> Vec<BiomeCoverage> {
+    let counts = state.biome_counts.read().unwrap();
+    let total = counts.iter().sum::<u64>().max(1) as f64;
+    let color_table = &state.options.color_table;
+    let sea = &color_table[color_table.sea_level as usize];
+
+    let mut retval = vec![BiomeCoverage {
+        code: None,
+        name: "Water".into(),
+        color: [sea.red, sea.green, sea.blue],
+        pixels: counts[0],
+        percentage: 100.0 * counts[0] as f64 / total,
+    }];
+    // Köppen zones are counted one past their index, as zero is water
+    let classes = if state.options.show_koppen {
+        KOPPEN_ZONES
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.code.to_string(), x.name.to_string(), &x.color, i + 1))
+            .collect::<Vec<_>>()
+    } else {
+        state
+            .options
+            .biome_table
+            .types
+            .iter()
+            .map(|x| ((x.code as char).to_string(), x.name.clone(), &x.color, x.code as usize))
+            .collect::<Vec<_>>()
+    };
+    for (code, name, color, index) in classes {
+        retval.push(BiomeCoverage {
+            code: Some(code),
+            name,
+            color: [color.red, color.green, color.blue],
+            pixels: counts[index],
+            percentage: 100.0 * counts[index] as f64 / total,
+        });
+    }
+    retval
+}
+
+pub(super) fn write_sidecar<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
+    let coverage = get_coverage(&state);
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"map_pixels\": {},", coverage.iter().map(|x| x.pixels).sum::<u64>())?;
+    writeln!(writer, "  \"biomes\": [")?;
+    for (i, biome) in coverage.iter().enumerate() {
+        let code = match &biome.code {
+            Some(code) => get_json_string(code),
+            None => "null".into(),
+        };
+        let [red, green, blue] = biome.color;
+        let separator = if i + 1 < coverage.len() { "," } else { "" };
+        writeln!(
+            writer,
+            "    {{\"code\": {code}, \"name\": {}, \"color\": \"#{red:02x}{green:02x}{blue:02x}\", \
+             \"pixels\": {}, \"coverage\": {:.3}}}{separator}",
+            get_json_string(&biome.name), biome.pixels, biome.percentage
+        )?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(super) fn write_legend<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
+    let scale = 2;
+    let padding = 8;
+    let swatch = GLYPH_HEIGHT * scale + 4;
+    let row_height = swatch + padding;
+
+    let lines = get_coverage(&state)
+        .into_iter()
+        .map(|x| {
+            let code = x.code.unwrap_or_default();
+            (x.color, format!("{code} {}  {:.1}%", x.name, x.percentage))
+        })
+        .collect::<Vec<([u8; 3], String)>>();
+
+    let text_width = lines.iter().map(|x| get_text_width(&x.1, scale)).max().unwrap_or(0);
+    let width = 3 * padding + swatch + text_width;
+    let height = padding + lines.len() * row_height;
+    let mut pixels = vec![255u8; 3 * width * height];
+
+    for (i, (color, text)) in lines.iter().enumerate() {
+        let top = padding + i * row_height;
+        // swatch with a black border
+        for y in top..top + swatch {
+            for x in padding..padding + swatch {
+                let border = y == top || y == top + swatch - 1 || x == padding || x == padding + swatch - 1;
+                let offset = 3 * (y * width + x);
+                pixels[offset..offset + 3].copy_from_slice(if border { &[0, 0, 0] } else { color });
+            }
+        }
+        draw_text(&mut pixels, width, 2 * padding + swatch, top + 2, text, scale, [0, 0, 0]);
+    }
+
+    png::write_rgb(writer, width, height, &pixels)
+}
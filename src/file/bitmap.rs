@@ -1,4 +1,4 @@
-use crate::file::ColorMode;
+use crate::file::{get_monochrome_bits, ColorMode};
 use crate::get_commandline_footer;
 use crate::render::RenderState;
 use std::io;
@@ -73,9 +73,10 @@ pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Res
 
     // writing our indexed colors
     if let ColorMode::Monochrome = color_mode {
+        let (dark, light) = state.options.color_table.get_monochrome_colors().unwrap();
         writer.write_all(&[
-            0, 0, 0, 0, // black
-            255, 255, 255, 255, // white
+            dark.blue, dark.green, dark.red, 0, // index 0
+            light.blue, light.green, light.red, 0, // index 1
         ])?;
     };
     // write pixels
@@ -105,22 +106,18 @@ pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Res
             }
         }
         ColorMode::Monochrome => {
-            // we fit 32 pixels per 4 byte cluster
-            for v in (0..canvas.len()).rev() {
-                for h in (0..state.options.slicing.height).rev() {
-                    for w in (0..padded_width).step_by(32) {
+            let bits = get_monochrome_bits(&state);
+            // we fit 32 pixels per 4 byte cluster, leftmost pixel in the highest bit
+            for v in canvas.iter().rev() {
+                for h in v.iter().rev() {
+                    for w in (0..padded_width as usize).step_by(32) {
                         let mut quad = 0u32;
-                        let stop = (state.options.slicing.width as u32 - w).min(32);
-
-                        for s in 0..stop {
-                            let color_index = canvas[v][(w + s) as usize][h] as usize;
-                            if (w + s) < state.options.slicing.width as u32
-                                && state.options.color_table[color_index].red != 0
-                            {
+                        for (s, color_index) in h.iter().skip(w).take(32).enumerate() {
+                            if bits[*color_index as usize] {
                                 quad |= 0b1 << (31 - s);
                             }
                         }
-                        writer.write_all(&quad.to_le_bytes())?;
+                        writer.write_all(&quad.to_be_bytes())?;
                     }
                 }
             }
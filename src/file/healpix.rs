@@ -0,0 +1,12 @@
+use std::io;
+use std::io::Write;
+
+/// Writes the altitude of each pixel of a HEALPix grid as a big-endian 32-bit float, the byte
+/// order of the FITS files HEALPix maps are usually kept in
+pub(super) fn write_raw<W: Write>(altitudes: &[f32], writer: &mut W) -> Result<(), io::Error> {
+    for altitude in altitudes {
+        writer.write_all(&altitude.to_be_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::render::landmass::Landmass;
+use std::io;
+use std::io::Write;
+
+pub(super) fn write_report<W: Write>(landmasses: &[Landmass], writer: &mut W) -> Result<(), io::Error> {
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"count\": {},", landmasses.len())?;
+    writeln!(writer, "  \"landmasses\": [")?;
+    for (i, x) in landmasses.iter().enumerate() {
+        let [left, top, right, bottom] = x.pixel_bounds;
+        let separator = if i + 1 < landmasses.len() { "," } else { "" };
+        writeln!(
+            writer,
+            "    {{\"id\": {}, \"pixels\": {}, \"surface_fraction\": {}, \
+             \"centroid\": {{\"latitude\": {:.3}, \"longitude\": {:.3}}}, \
+             \"bounds\": {{\"north\": {:.3}, \"south\": {:.3}, \"west\": {:.3}, \"east\": {:.3}}}, \
+             \"pixel_bounds\": {{\"left\": {left}, \"top\": {top}, \"right\": {right}, \"bottom\": {bottom}}}}}{separator}",
+            x.id,
+            x.pixels,
+            x.surface_fraction,
+            x.centroid_latitude,
+            x.centroid_longitude,
+            x.north,
+            x.south,
+            x.west,
+            x.east,
+        )?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(())
+}
@@ -1,21 +1,34 @@
-use crate::get_commandline_footer;
+use crate::{get_commandline_footer, parse_commandline_footer};
+use crate::file::{get_monochrome_bits, ColorMode};
+use crate::render::layers::Layer;
 use crate::render::RenderState;
+use crate::util::Vec2D;
 use mtpng::encoder::{Encoder, Options};
 use mtpng::{ColorType, CompressionLevel, Header};
 use std::io;
 use std::io::Write;
 use std::sync::Arc;
 
-// TODO: Add indexed palette if no shading exists, and embed commandline
+// PNG palettes are limited to 8-bit indices
+const MAX_PALETTE_SIZE: usize = 256;
+
+pub(super) const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
 pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
     // PNG file specification
     // https://en.wikipedia.org/wiki/PNG
 
-    let _cmdline = get_commandline_footer();
+    let cmdline = get_commandline_footer();
+    let color_mode = state.get_color_mode();
+    let use_palette = uses_palette(&state);
 
     let mut header = Header::new();
     header.set_size(state.options.slicing.width as u32, state.options.slicing.height as u32)?;
-    header.set_color(ColorType::Truecolor, 8)?;
+    match color_mode {
+        ColorMode::Monochrome => header.set_color(ColorType::IndexedColor, 1)?,
+        ColorMode::Color if use_palette => header.set_color(ColorType::IndexedColor, 8)?,
+        ColorMode::Color => header.set_color(ColorType::Truecolor, 8)?,
+    }
 
     let mut options = Options::new();
     options.set_compression_level(CompressionLevel::Default)?;
@@ -23,27 +36,295 @@ pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Res
 
     let mut encoder = Encoder::new(writer, &options);
     encoder.write_header(&header)?;
-    
-    // TODO: detect if any color-altering options are enabled, and switch to writing a palette instead 
+
+    if let ColorMode::Monochrome = color_mode {
+        let (dark, light) = state.options.color_table.get_monochrome_colors().unwrap();
+        encoder.write_palette(&[dark.red, dark.green, dark.blue, light.red, light.green, light.blue])?;
+    } else if use_palette {
+        let color_table = &state.options.color_table;
+        let mut palette: Vec<u8> = Vec::with_capacity(color_table.len() * 3);
+        for i in 0..color_table.len() {
+            let color = &color_table[i];
+            palette.extend_from_slice(&[color.red, color.green, color.blue]);
+        }
+        encoder.write_palette(&palette)?;
+    }
+
+    let (tag, text) = get_text_chunk("Comment", &cmdline);
+    encoder.write_chunk(tag, &text)?;
 
     let canvas = state.canvas.read().unwrap();
-    
+    let shading = state.shading.read().unwrap();
+
     let height = state.options.slicing.height;
     let width = state.options.slicing.width;
-    let mut line: Vec<u8> = Vec::with_capacity(height * width * 3);
-    for v in canvas.iter() {
-        for h in v.iter() {
-            for w in h.iter() {
-                let color_index = *w as usize;
+
+    if let ColorMode::Monochrome = color_mode {
+        // rows are packed 8 pixels per byte, leftmost pixel in the highest bit
+        let bits = get_monochrome_bits(&state);
+        let mut line: Vec<u8> = Vec::with_capacity(height * width.div_ceil(8));
+        for h in canvas.iter().flatten() {
+            for pixels in h.chunks(8) {
+                let mut byte = 0u8;
+                for (s, color_index) in pixels.iter().enumerate() {
+                    if bits[*color_index as usize] {
+                        byte |= 0b1000_0000 >> s;
+                    }
+                }
+                line.push(byte);
+            }
+        }
+        encoder.write_image_rows(&line)?;
+        encoder.finish()?;
+        return Ok(());
+    }
+
+    let mut line: Vec<u8> = if use_palette {
+        Vec::with_capacity(height * width)
+    } else {
+        Vec::with_capacity(height * width * 3)
+    };
+    for (vi, v) in canvas.iter().enumerate() {
+        for (hi, h) in v.iter().enumerate() {
+            for (wi, color_index) in h.iter().enumerate().map(|x| (x.0, *x.1 as usize)) {
+                if use_palette {
+                    line.push(color_index as u8);
+                    continue;
+                }
                 let color = &state.options.color_table[color_index];
-                line.push(color.red);
-                line.push(color.green);
-                line.push(color.blue);
+                if state.options.shading_level > 0 {
+                    let shade = shading[vi][hi][wi] as u32;
+                    line.push(((shade * color.red as u32) / 150).min(255) as u8);
+                    line.push(((shade * color.green as u32) / 150).min(255) as u8);
+                    line.push(((shade * color.blue as u32) / 150).min(255) as u8);
+                } else {
+                    line.push(color.red);
+                    line.push(color.green);
+                    line.push(color.blue);
+                }
             }
         }
     }
     encoder.write_image_rows(&line)?;
-    //encoder.write_chunk(b"CMDL", cmdline.as_bytes())?;
     encoder.finish()?;
     Ok(())
 }
+
+pub(super) fn write_normal_map<W: Write>(
+    state: Arc<RenderState>,
+    writer: &mut W,
+) -> Result<(), io::Error> {
+    let line = state
+        .normal_map
+        .read()
+        .unwrap()
+        .iter()
+        .flatten()
+        .flatten()
+        .flatten()
+        .copied()
+        .collect::<Vec<u8>>();
+    write_rgb(writer, state.options.slicing.width, state.options.slicing.height, &line)
+}
+
+/// Writes packed RGB pixels as a truecolor PNG
+pub(super) fn write_rgb<W: Write>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> Result<(), io::Error> {
+    let mut header = Header::new();
+    header.set_size(width as u32, height as u32)?;
+    header.set_color(ColorType::Truecolor, 8)?;
+
+    let mut options = Options::new();
+    options.set_compression_level(CompressionLevel::Default)?;
+    options.set_streaming(true)?;
+
+    let mut encoder = Encoder::new(writer, &options);
+    encoder.write_header(&header)?;
+
+    let (tag, text) = get_text_chunk("Comment", &get_commandline_footer());
+    encoder.write_chunk(tag, &text)?;
+
+    encoder.write_image_rows(pixels)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Writes landmass labels as 16-bit grayscale, saturating above 65535 landmasses
+pub(super) fn write_labels<W: Write>(labels: &Vec2D<u32>, writer: &mut W) -> Result<(), io::Error> {
+    let mut header = Header::new();
+    header.set_size(labels.first().map_or(0, |x| x.len()) as u32, labels.len() as u32)?;
+    header.set_color(ColorType::Greyscale, 16)?;
+
+    let mut options = Options::new();
+    options.set_compression_level(CompressionLevel::Default)?;
+    options.set_streaming(true)?;
+
+    let mut encoder = Encoder::new(writer, &options);
+    encoder.write_header(&header)?;
+
+    let (tag, text) = get_text_chunk("Comment", &get_commandline_footer());
+    encoder.write_chunk(tag, &text)?;
+
+    let line = labels
+        .iter()
+        .flatten()
+        .flat_map(|x| ((*x).min(u16::MAX as u32) as u16).to_be_bytes())
+        .collect::<Vec<u8>>();
+    encoder.write_image_rows(&line)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Writes a continuous layer as 16-bit grayscale stretched over its range, or the biome layer
+/// as indexed color. Zero is reserved for pixels outside the map.
+pub(super) fn write_layer<W: Write>(
+    state: Arc<RenderState>,
+    index: usize,
+    writer: &mut W,
+) -> Result<(), io::Error> {
+    let layer = state.options.layers[index];
+    let layers = state.layers.read().unwrap();
+    let values = layers
+        .iter()
+        .flat_map(|x| x[index].iter().flatten())
+        .copied()
+        .collect::<Vec<f32>>();
+
+    let mut header = Header::new();
+    header.set_size(state.options.slicing.width as u32, state.options.slicing.height as u32)?;
+    if let Layer::Biome = layer {
+        header.set_color(ColorType::IndexedColor, 8)?;
+    } else {
+        header.set_color(ColorType::Greyscale, 16)?;
+    }
+
+    let mut options = Options::new();
+    options.set_compression_level(CompressionLevel::Default)?;
+    options.set_streaming(true)?;
+
+    let mut encoder = Encoder::new(writer, &options);
+    encoder.write_header(&header)?;
+
+    let (tag, text) = get_text_chunk("Comment", &get_commandline_footer());
+    encoder.write_chunk(tag, &text)?;
+
+    let line: Vec<u8> = if let Layer::Biome = layer {
+        let color_table = &state.options.color_table;
+        let mut palette = vec![color_table[color_table.back as usize].clone()];
+        palette.push(color_table[color_table.sea_level as usize].clone());
+        palette.extend(state.options.biome_table.types.iter().map(|x| x.color.clone()));
+        encoder.write_palette(
+            &palette
+                .iter()
+                .flat_map(|x| [x.red, x.green, x.blue])
+                .collect::<Vec<u8>>(),
+        )?;
+
+        values
+            .iter()
+            .map(|x| match x {
+                x if x.is_nan() => 0,
+                0.0 => 1,
+                x => {
+                    let types = &state.options.biome_table.types;
+                    2 + types.iter().position(|c| c.code as f32 == *x).unwrap_or(0) as u8
+                }
+            })
+            .collect()
+    } else {
+        let (min, max) = values
+            .iter()
+            .filter(|x| !x.is_nan())
+            .fold((f32::MAX, f32::MIN), |(min, max), x| (min.min(*x), max.max(*x)));
+        let range = if max > min { max - min } else { 1.0 };
+        let (tag, text) = get_text_chunk(
+            "Description",
+            &format!("{} from {min} (1) to {max} (65535), 0 is outside the map", layer.get_name()),
+        );
+        encoder.write_chunk(tag, &text)?;
+
+        values
+            .iter()
+            .flat_map(|x| {
+                if x.is_nan() {
+                    0u16
+                } else {
+                    1 + ((x - min) / range * 65534.0).round() as u16
+                }
+                .to_be_bytes()
+            })
+            .collect()
+    };
+    encoder.write_image_rows(&line)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Shaded images need per-pixel colors, so a palette is only possible without shading
+fn uses_palette(state: &RenderState) -> bool {
+    state.options.shading_level == 0 && state.options.color_table.len() <= MAX_PALETTE_SIZE
+}
+
+/// Builds a tEXt chunk, or an uncompressed iTXt chunk if the text is not Latin-1 safe
+fn get_text_chunk(keyword: &str, text: &str) -> (&'static [u8], Vec<u8>) {
+    let mut data: Vec<u8> = Vec::with_capacity(keyword.len() + text.len() + 5);
+    data.extend_from_slice(keyword.as_bytes());
+    data.push(0);
+    if text.is_ascii() {
+        data.extend_from_slice(text.as_bytes());
+        (b"tEXt", data)
+    } else {
+        // compression flag, compression method, empty language tag, empty translated keyword
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(text.as_bytes());
+        (b"iTXt", data)
+    }
+}
+
+/// Searches the text chunks of a PNG file for an embedded command line
+pub(super) fn read_commandline(data: &[u8]) -> Result<Option<Vec<String>>, io::Error> {
+    let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "truncated PNG chunk");
+    let mut index = SIGNATURE.len();
+
+    // each chunk is a 4 byte length, 4 byte tag, data, and a 4 byte crc
+    while index + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[index..index + 4].try_into().unwrap()) as usize;
+        let tag = &data[index + 4..index + 8];
+        let chunk = data.get(index + 8..index + 8 + length).ok_or_else(truncated)?;
+        index += length + 12;
+
+        let text = match tag {
+            b"tEXt" => chunk.splitn(2, |x| *x == 0).nth(1),
+            // keyword, compression flag and method, language tag, translated keyword, text
+            // only uncompressed international text is supported
+            b"iTXt" => chunk
+                .iter()
+                .position(|x| *x == 0)
+                .filter(|x| chunk.get(x + 1) == Some(&0))
+                .and_then(|x| chunk.get(x + 3..))
+                .and_then(|x| x.splitn(3, |x| *x == 0).nth(2)),
+            b"IEND" => break,
+            _ => None,
+        };
+        if let Some(args) = text.and_then(|x| parse_commandline_footer(&String::from_utf8_lossy(x))) {
+            return Ok(Some(args));
+        }
+    }
+    Ok(None)
+}
+
+#[test]
+fn test_text_chunk() {
+    let (tag, data) = get_text_chunk("Comment", "Command line:\n-s 0.5\n");
+    assert_eq!(tag, b"tEXt");
+    assert_eq!(data, b"Comment\0Command line:\n-s 0.5\n");
+
+    let (tag, data) = get_text_chunk("Comment", "-C Olsson\u{e9}.col");
+    assert_eq!(tag, b"iTXt");
+    assert_eq!(&data[..12], b"Comment\0\0\0\0\0");
+    assert_eq!(&data[12..], "-C Olsson\u{e9}.col".as_bytes());
+}
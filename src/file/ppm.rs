@@ -1,3 +1,4 @@
+use crate::file::{get_monochrome_bits, ColorMode};
 use crate::get_commandline_footer;
 use crate::render::RenderState;
 use std::io;
@@ -5,6 +6,10 @@ use std::io::Write;
 use std::sync::Arc;
 
 pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
+    if let ColorMode::Monochrome = state.get_color_mode() {
+        return write_pbm(state, writer);
+    }
+
     writeln!(writer, "P6\n{}\n{}\n255",
              state.options.slicing.width as u32, state.options.slicing.height as u32)?;
 
@@ -34,3 +39,28 @@ pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Res
     writer.flush()?;
     Ok(())
 }
+
+// Binary PBM, where a set bit is black
+fn write_pbm<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
+    writeln!(writer, "P4\n{}\n{}",
+             state.options.slicing.width as u32, state.options.slicing.height as u32)?;
+
+    let bits = get_monochrome_bits(&state);
+    for h in state.canvas.read().unwrap().iter().flatten() {
+        // rows are packed 8 pixels per byte, leftmost pixel in the highest bit
+        for pixels in h.chunks(8) {
+            let mut byte = 0u8;
+            for (s, color_index) in pixels.iter().enumerate() {
+                if !bits[*color_index as usize] {
+                    byte |= 0b1000_0000 >> s;
+                }
+            }
+            writer.write_all(&[byte])?;
+        }
+    }
+    writeln!(writer, "#fractal planet image")?;
+    writeln!(writer, "#{}", get_commandline_footer())?;
+
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use crate::render::hydrology::River;
+use std::io;
+use std::io::Write;
+
+/// Writes rivers as a GeoJSON collection of lines, with positions as longitude then latitude
+pub(super) fn write_geojson<W: Write>(rivers: &[River], writer: &mut W) -> Result<(), io::Error> {
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"type\": \"FeatureCollection\",")?;
+    writeln!(writer, "  \"features\": [")?;
+    for (i, river) in rivers.iter().enumerate() {
+        let coordinates = river
+            .points
+            .iter()
+            .map(|(latitude, longitude)| format!("[{longitude:.4}, {latitude:.4}]"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let separator = if i + 1 < rivers.len() { "," } else { "" };
+        writeln!(
+            writer,
+            "    {{\"type\": \"Feature\", \"properties\": {{\"discharge\": {:.3}}}, \
+             \"geometry\": {{\"type\": \"LineString\", \"coordinates\": [{coordinates}]}}}}{separator}",
+            river.discharge,
+        )?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(())
+}
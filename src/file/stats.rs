@@ -0,0 +1,120 @@
+use crate::file::get_json_string;
+use crate::render::stats::PlanetStats;
+use std::io;
+use std::io::Write;
+
+pub(super) fn write_text<W: Write>(stats: &PlanetStats, writer: &mut W) -> Result<(), io::Error> {
+    writeln!(
+        writer,
+        "Samples: {} (evenly spread over the sphere)",
+        stats.samples
+    )?;
+    writeln!(writer, "Land: {:.2}%", 100.0 * stats.land_fraction)?;
+    writeln!(writer, "Ocean: {:.2}%", 100.0 * (1.0 - stats.land_fraction))?;
+    writeln!(
+        writer,
+        "Altitude: min {:.5}, max {:.5}, mean {:.5}",
+        stats.min_altitude, stats.max_altitude, stats.mean_altitude
+    )?;
+    writeln!(
+        writer,
+        "Temperature: mean {:.1} C, over land {:.1} C",
+        stats.mean_temperature, stats.mean_land_temperature
+    )?;
+    writeln!(
+        writer,
+        "Rainfall: mean {:.0} mm/year, over land {:.0} mm/year",
+        stats.mean_rainfall, stats.mean_land_rainfall
+    )?;
+
+    writeln!(writer, "\nAltitude histogram (bin start, % of surface):")?;
+    for (altitude, fraction) in &stats.histogram {
+        writeln!(writer, "  {altitude:>9.5}  {:>6.2}%", 100.0 * fraction)?;
+    }
+
+    writeln!(writer, "\nHypsometric curve (% of surface above altitude):")?;
+    for (percent, altitude) in &stats.hypsometric_curve {
+        writeln!(writer, "  {percent:>3}%  {altitude:.5}")?;
+    }
+
+    writeln!(writer, "\nBiomes (% of land):")?;
+    for (code, name, fraction) in &stats.biomes {
+        writeln!(writer, "  {code:<3} {:>6.2}%  {name}", 100.0 * fraction)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub(super) fn write_json<W: Write>(stats: &PlanetStats, writer: &mut W) -> Result<(), io::Error> {
+    let histogram = stats
+        .histogram
+        .iter()
+        .map(|(altitude, fraction)| {
+            format!("{{\"altitude\": {altitude}, \"fraction\": {fraction}}}")
+        })
+        .collect::<Vec<String>>();
+    let hypsometric_curve = stats
+        .hypsometric_curve
+        .iter()
+        .map(|(percent, altitude)| {
+            format!("{{\"percent_above\": {percent}, \"altitude\": {altitude}}}")
+        })
+        .collect::<Vec<String>>();
+    let biomes = stats
+        .biomes
+        .iter()
+        .map(|(code, name, fraction)| {
+            format!(
+                "{{\"code\": {}, \"name\": {}, \"land_fraction\": {fraction}}}",
+                get_json_string(code),
+                get_json_string(name)
+            )
+        })
+        .collect::<Vec<String>>();
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"samples\": {},", stats.samples)?;
+    writeln!(writer, "  \"land_fraction\": {},", stats.land_fraction)?;
+    writeln!(
+        writer,
+        "  \"ocean_fraction\": {},",
+        1.0 - stats.land_fraction
+    )?;
+    writeln!(writer, "  \"min_altitude\": {},", stats.min_altitude)?;
+    writeln!(writer, "  \"max_altitude\": {},", stats.max_altitude)?;
+    writeln!(writer, "  \"mean_altitude\": {},", stats.mean_altitude)?;
+    writeln!(
+        writer,
+        "  \"mean_temperature_celsius\": {},",
+        stats.mean_temperature
+    )?;
+    writeln!(
+        writer,
+        "  \"mean_land_temperature_celsius\": {},",
+        stats.mean_land_temperature
+    )?;
+    writeln!(writer, "  \"mean_rainfall_mm\": {},", stats.mean_rainfall)?;
+    writeln!(
+        writer,
+        "  \"mean_land_rainfall_mm\": {},",
+        stats.mean_land_rainfall
+    )?;
+    writeln!(
+        writer,
+        "  \"altitude_histogram\": [\n    {}\n  ],",
+        histogram.join(",\n    ")
+    )?;
+    writeln!(
+        writer,
+        "  \"hypsometric_curve\": [\n    {}\n  ],",
+        hypsometric_curve.join(",\n    ")
+    )?;
+    writeln!(
+        writer,
+        "  \"biomes\": [\n    {}\n  ]",
+        biomes.join(",\n    ")
+    )?;
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(())
+}
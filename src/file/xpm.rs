@@ -1,19 +1,31 @@
-use crate::color::Color;
+use crate::color::{Color, ColorTable};
+use crate::file::{get_monochrome_bits, ColorMode};
 use crate::get_commandline_footer;
 use crate::render::RenderState;
 use std::io;
 use std::io::Write;
 use std::sync::Arc;
 
+//Character table for XPM output
+const CHARS: &[u8] = b"@$.,:;-+=#*&ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
 fn get_chars_per_pixel(color_table_len: usize, chars: &[u8]) -> u8 {
-    (color_table_len as f64).log(chars.len() as f64).ceil() as u8
+    let mut chars_per_pixel = 1;
+    let mut capacity = chars.len();
+    while capacity < color_table_len {
+        chars_per_pixel += 1;
+        capacity *= chars.len();
+    }
+    chars_per_pixel
 }
 
 #[test]
 fn test_chars_per_pixel() {
     let chars = b"@$.,:;-+=#*&ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
     assert_eq!(get_chars_per_pixel(63, chars), 1);
+    assert_eq!(get_chars_per_pixel(64, chars), 1);
     assert_eq!(get_chars_per_pixel(255, chars), 2);
+    assert_eq!(get_chars_per_pixel(4096, chars), 2);
     assert_eq!(get_chars_per_pixel(5000, chars), 3);
     assert_eq!(get_chars_per_pixel(65535, chars), 3);
 }
@@ -22,48 +34,165 @@ fn get_chars(chars: &[u8], color_index: usize, chars_per_pixel: u8) -> String {
     let mut color_index = color_index;
     let mut retval: String = String::with_capacity(chars_per_pixel as usize);
     for _ in 0..chars_per_pixel {
-        retval.push(chars[color_index & 0b111111] as char);
+        retval.push(chars[color_index % chars.len()] as char);
         color_index /= chars.len();
     }
     retval
 }
 
-// TODO: Someday, add monochrome saving, perhaps
 pub(super) fn write_to<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
-    //Character table for XPM output
-    let chars = b"@$.,:;-+=#*&ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
-    let cmdline = get_commandline_footer();
-    let chars_per_pixel = get_chars_per_pixel(state.options.color_table.len(), chars);
+    if let ColorMode::Monochrome = state.get_color_mode() {
+        return write_monochrome(state, writer);
+    }
+
+    let canvas = state.canvas.read().unwrap();
+    write_pixels(
+        writer,
+        &state.options.color_table,
+        state.options.slicing.width,
+        &canvas.iter().flatten().collect::<Vec<&Vec<u16>>>(),
+        &get_commandline_footer(),
+    )
+}
+
+// XPM3 format specification
+// https://www.x.org/docs/XPM/xpm.pdf
+fn write_pixels<W: Write>(
+    writer: &mut W,
+    color_table: &ColorTable,
+    width: usize,
+    rows: &[&Vec<u16>],
+    cmdline: &str,
+) -> Result<(), io::Error> {
+    let chars_per_pixel = get_chars_per_pixel(color_table.len(), CHARS);
 
     writeln!(writer, "/* XPM */")?;
-    writeln!(writer, "/* Command line: */")?;
     writeln!(writer, "/* {}*/", cmdline)?;
     writeln!(writer, "static char *xpmdata[] = {{")?;
     writeln!(writer, "/* width height ncolors chars_per_pixel */")?;
     writeln!(
         writer,
         "\"{} {} {} {}\",",
-        state.options.slicing.width,
-        state.options.slicing.height,
-        state.options.color_table.len(),
+        width,
+        rows.len(),
+        color_table.len(),
         chars_per_pixel
     )?;
 
     writeln!(writer, "/* colors */")?;
-    for i in 0..state.options.color_table.len() {
-        let Color { red, green, blue } = &state.options.color_table[i];
-        writeln!(writer, "\"{} c #{red:2x}{green:2x}{blue:2x}\",", get_chars(chars, i, chars_per_pixel))?;
+    let color_chars = (0..color_table.len())
+        .map(|i| get_chars(CHARS, i, chars_per_pixel))
+        .collect::<Vec<String>>();
+    for (i, chars) in color_chars.iter().enumerate() {
+        let Color { red, green, blue } = &color_table[i];
+        writeln!(writer, "\"{chars} c #{red:02x}{green:02x}{blue:02x}\",")?;
     }
 
     writeln!(writer, "/* pixels */")?;
-    for v in state.canvas.read().unwrap().iter() {
-        for h in v.iter() {
-            writeln!(writer, "\"")?;
-            for color_index in h.iter().map(|x| *x as usize) {
-                writeln!(writer, "{}", get_chars(chars, color_index, chars_per_pixel))?;
-            }
-            writeln!(writer, "\",")?;
+    let mut line = String::with_capacity(width * chars_per_pixel as usize);
+    for (hi, h) in rows.iter().enumerate() {
+        line.clear();
+        for color_index in h.iter().map(|x| *x as usize) {
+            line.push_str(&color_chars[color_index]);
         }
+        let separator = if hi + 1 < rows.len() { "," } else { "" };
+        writeln!(writer, "\"{line}\"{separator}")?;
+    }
+    writeln!(writer, "}};")?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_xpm_round_trip() {
+    use std::collections::HashMap;
+
+    let mut color_table = ColorTable::new(300);
+    for i in 0..color_table.len() {
+        color_table[i] = Color::from_colors(i as u8, (i / 2) as u8, 7);
+    }
+    let canvas: Vec<Vec<u16>> = (0..5)
+        .map(|h| (0..9).map(|w| (h * 61 + w * 7) % 300).collect())
+        .collect();
+
+    let mut output: Vec<u8> = vec![];
+    write_pixels(
+        &mut output,
+        &color_table,
+        9,
+        &canvas.iter().collect::<Vec<&Vec<u16>>>(),
+        "Command line:\n-x\n",
+    )
+    .unwrap();
+    let text = String::from_utf8(output).unwrap();
+
+    // every string in the array, in order: values, colors, then pixels
+    let strings = text
+        .lines()
+        .filter(|x| x.starts_with('"'))
+        .map(|x| x.trim_end_matches(',').trim_matches('"'))
+        .collect::<Vec<&str>>();
+    let values = strings[0]
+        .split(' ')
+        .map(|x| x.parse::<usize>().unwrap())
+        .collect::<Vec<usize>>();
+    let (width, height, ncolors, cpp) = (values[0], values[1], values[2], values[3]);
+    assert_eq!((width, height, ncolors, cpp), (9, 5, 300, 2));
+    assert!(text.trim_end().ends_with("\"\n};"));
+
+    let mut colors: HashMap<&str, u16> = HashMap::new();
+    for (i, line) in strings[1..=ncolors].iter().enumerate() {
+        let (chars, definition) = line.split_at(cpp);
+        let hex = definition.strip_prefix(" c #").unwrap();
+        assert_eq!(hex.len(), 6);
+        let red = u8::from_str_radix(&hex[0..2], 16).unwrap();
+        let green = u8::from_str_radix(&hex[2..4], 16).unwrap();
+        assert_eq!(Color::from_colors(red, green, 7), color_table[i]);
+        assert!(colors.insert(chars, i as u16).is_none());
+    }
+
+    let parsed: Vec<Vec<u16>> = strings[ncolors + 1..]
+        .iter()
+        .map(|x| {
+            assert_eq!(x.len(), width * cpp);
+            (0..width).map(|w| colors[&x[w * cpp..(w + 1) * cpp]]).collect()
+        })
+        .collect();
+    assert_eq!(parsed, canvas);
+}
+
+// Two color XPM, with black and white as the monochrome visual
+fn write_monochrome<W: Write>(state: Arc<RenderState>, writer: &mut W) -> Result<(), io::Error> {
+    let cmdline = get_commandline_footer();
+    let (dark, light) = state.options.color_table.get_monochrome_colors().unwrap();
+    let bits = get_monochrome_bits(&state);
+
+    writeln!(writer, "/* XPM */")?;
+    writeln!(writer, "/* {}*/", cmdline)?;
+    writeln!(writer, "static char *xpmdata[] = {{")?;
+    writeln!(writer, "/* width height ncolors chars_per_pixel */")?;
+    writeln!(
+        writer,
+        "\"{} {} 2 1\",",
+        state.options.slicing.width, state.options.slicing.height
+    )?;
+
+    writeln!(writer, "/* colors */")?;
+    let Color { red, green, blue } = dark;
+    writeln!(writer, "\". c #{red:02x}{green:02x}{blue:02x} m black\",")?;
+    let Color { red, green, blue } = light;
+    writeln!(writer, "\"# c #{red:02x}{green:02x}{blue:02x} m white\",")?;
+
+    writeln!(writer, "/* pixels */")?;
+    let canvas = state.canvas.read().unwrap();
+    let mut rows = canvas.iter().flatten().peekable();
+    while let Some(h) = rows.next() {
+        let line = h
+            .iter()
+            .map(|x| if bits[*x as usize] { '#' } else { '.' })
+            .collect::<String>();
+        let separator = if rows.peek().is_some() { "," } else { "" };
+        writeln!(writer, "\"{line}\"{separator}")?;
     }
     writeln!(writer, "}};")?;
     writer.flush()?;
@@ -145,4 +145,14 @@ pub fn common_vertex_from_point(cp: &LatLong, x: &f64, y: &f64, z: &f64) -> Vert
         cp.lat_cos * y - cp.lat_sin * z,
         -cp.long_sin * x + cp.long_cos * cp.lat_sin * y + cp.long_cos * cp.lat_cos * z,
     )
-}
\ No newline at end of file
+}
+
+/// Turns a point on the planet back into the frame of common_vertex_from_point
+#[inline(always)]
+pub fn point_from_common_vertex(cp: &LatLong, p: &Vertex) -> (f64, f64, f64) {
+    (
+        cp.long_cos * p.x - cp.long_sin * p.z,
+        cp.long_sin * cp.lat_sin * p.x + cp.lat_cos * p.y + cp.long_cos * cp.lat_sin * p.z,
+        cp.long_sin * cp.lat_cos * p.x - cp.lat_sin * p.y + cp.long_cos * cp.lat_cos * p.z,
+    )
+}
@@ -10,7 +10,13 @@ mod projection;
 
 use clap::Parser;
 use std::env;
+use std::iter;
 use std::mem::size_of;
+use std::sync::OnceLock;
+
+/// The effective command line of this render, which may differ from the process arguments
+/// when parameters were read back from an image
+static COMMAND_LINE: OnceLock<Vec<String>> = OnceLock::new();
 
 fn main() {
     if size_of::<usize>() < size_of::<u64>() {
@@ -19,9 +25,67 @@ fn main() {
     }
     let args = Args::parse();
 
+    let args = match &args.from_image {
+        Some(filename) => match file::read_commandline(filename) {
+            Ok(stored) => {
+                // the stored output file is the image being read, so it is only written to if
+                // named again, and arguments given alongside --from-image override the stored ones
+                let mut stored = strip_option(stored.into_iter(), "-o");
+                stored.extend(strip_option(env::args().skip(1), "--from-image"));
+                let _ = COMMAND_LINE.set(stored.clone());
+                Args::parse_from(iter::once(env::args().next().unwrap_or_default()).chain(stored))
+            }
+            Err(e) => {
+                eprintln!("Unable to read parameters from {filename}: {e}");
+                return;
+            }
+        },
+        None => args,
+    };
+
     render::execute(args);
 }
 
+/// Removes an option and its value from a list of arguments, including a short option with its
+/// value attached
+fn strip_option(args: impl Iterator<Item = String>, name: &str) -> Vec<String> {
+    let attached = if name.starts_with("--") { format!("{name}=") } else { name.to_string() };
+    let mut retval = vec![];
+    let mut skip_next = false;
+    for arg in args {
+        if skip_next {
+            skip_next = false;
+        } else if arg == name {
+            skip_next = true;
+        } else if !arg.starts_with(&attached) {
+            retval.push(arg);
+        }
+    }
+    retval
+}
+
+#[test]
+fn test_strip_from_image() {
+    let args = ["--from-image", "old.png", "-w", "4000", "--from-image=x.bmp", "-p", "M"];
+    assert_eq!(
+        strip_option(args.iter().map(|x| x.to_string()), "--from-image"),
+        vec!["-w", "4000", "-p", "M"]
+    );
+    let args = ["-o", "old", "-w", "4000", "-oother", "-p", "M"];
+    assert_eq!(strip_option(args.iter().map(|x| x.to_string()), "-o"), vec!["-w", "4000", "-p", "M"]);
+}
+
+#[test]
+fn test_bbox_overrides() {
+    // the later of --bbox and the center or magnification wins, as when rendering --from-image
+    let args = Args::parse_from(["landgen", "-C", "x.col", "-m", "2", "-L", "10", "--bbox", "-20,30,40,70"]);
+    assert!(args.bounding_box.is_some());
+    assert_eq!((args.magnification, args.latitude), (1.0, 0.0));
+    let args = Args::parse_from(["landgen", "-C", "x.col", "--bbox", "-20,30,40,70", "-m", "3"]);
+    assert!(args.bounding_box.is_none());
+    assert_eq!(args.magnification, 3.0);
+}
+
 #[test]
 fn full_test_run() {
     use std::path::Path;
@@ -37,10 +101,26 @@ fn full_test_run() {
         height: 1000,
         width: 1000,
         projection: "m".into(),
+        goode_north_lobes: projection::goode::GoodeLobes::from_list(projection::goode::NORTH_LOBES)
+            .unwrap(),
+        goode_south_lobes: projection::goode::GoodeLobes::from_list(projection::goode::SOUTH_LOBES)
+            .unwrap(),
+        standard_parallels: None,
+        central_meridian: None,
+        scale_factor: 1.,
+        view_altitude: 1.,
+        view_tilt: 0.,
+        view_azimuth: 0.,
+        healpix_nside: None,
+        healpix_order: "ring".into(),
+        icosahedron: projection::icosahedral::IcosahedronOrientation::from_name("classic").unwrap(),
+        draw_face_edges: false,
         precision: "oooo".into(),
         longitude: -130.,
         latitude: 0.,
         magnification: 1.,
+        bounding_box: None,
+        fit_bbox_aspect: false,
         color_filename: color_file_path,
         seed: 0.7609952,
         output_file: Some("./test_output".to_string()),
@@ -48,9 +128,21 @@ fn full_test_run() {
         calculate_rainfall: false,
         latitude_color: 0,
         show_biomes: false,
+        biome_filename: None,
+        show_koppen: false,
+        obliquity: 23.44,
+        season: None,
         use_xpm_format: false,
         use_ppm_format: false,
         use_heightfield_format: false,
+        use_normal_map_format: false,
+        normal_map_strength: 1.,
+        layers: vec![],
+        write_biome_sidecar: false,
+        write_biome_legend: false,
+        stats_format: None,
+        write_landmass_report: false,
+        write_landmass_labels: false,
         use_png_format: true,
         use_bmp_format: true,
         map_rotation: vec![0., 0.],
@@ -65,6 +157,12 @@ fn full_test_run() {
         water_contour_lines: 2,
         make_wrinkly_map: false,
         initial_altitude: -0.02,
+        land_fraction: None,
+        erosion_iterations: 0,
+        erosion_strength: 1.,
+        draw_rivers: false,
+        river_threshold: 5000.,
+        draw_lakes: false,
         longitude_gridsize: 0.,
         latitude_gridsize: 0.,
         use_temperature: false,
@@ -74,6 +172,7 @@ fn full_test_run() {
         help: None,
         version: None,
         render_threads: 8,
+        from_image: None,
     };
 
     render::execute(args);
@@ -82,7 +181,8 @@ fn full_test_run() {
 #[derive(Parser)]
 #[clap(disable_help_flag = true)]
 #[clap(disable_version_flag = true)]
-#[command(version)]
+#[command(version, args_override_self = true)]
+#[command(group(clap::ArgGroup::new("land_classes").args(["show_biomes", "show_koppen"])))]
 struct Args {
     /// Prints this help message
     #[arg(long, action = clap::ArgAction::HelpLong)]
@@ -136,6 +236,23 @@ struct Args {
     )]
     latitude: f64,
 
+    /// Frame a region instead of centering on -l and -L with -m: the west, south, east and north
+    /// edges in degrees. The center and magnification are chosen so the region fills the image.
+    /// Whichever of this and -l, -L and -m is given last is used.
+    #[arg(
+        long = "bbox",
+        value_name = "west,south,east,north",
+        value_parser = projection::bounding_box::BoundingBox::from_list,
+        allow_hyphen_values = true,
+        overrides_with_all = ["longitude", "latitude", "magnification"],
+        verbatim_doc_comment
+    )]
+    bounding_box: Option<projection::bounding_box::BoundingBox>,
+
+    /// With --bbox, also change the height of the image to the shape of the region.
+    #[arg(long = "bbox-aspect", default_value_t = false)]
+    fit_bbox_aspect: bool,
+
     /// Degrees between vertical gridlines.
     #[arg(short = 'g', value_name = "gridsize", default_value_t = 0.0)]
     latitude_gridsize: f64,
@@ -150,6 +267,34 @@ struct Args {
     )]
     initial_altitude: f64,
 
+    /// Fraction of the surface to be land, from 0 to 1. This chooses the initial altitude.
+    #[arg(long = "land-fraction", value_name = "fraction", conflicts_with = "initial_altitude")]
+    land_fraction: Option<f64>,
+
+    /// Iterations of hydraulic and thermal erosion applied to the altitudes before coloring.
+    /// Each iteration runs a droplet for every few land pixels, then lets steep slopes slump.
+    #[arg(long = "erosion", value_name = "iterations", default_value_t = 0)]
+    erosion_iterations: u32,
+
+    /// Multiplies how much material each erosion iteration moves.
+    #[arg(long = "erosion-strength", value_name = "strength", default_value_t = 1.0)]
+    erosion_strength: f64,
+
+    /// Draws rivers in the color of the shallowest sea, and writes them as GeoJSON lines next to
+    /// the output file.
+    #[arg(long = "rivers", default_value_t = false)]
+    draw_rivers: bool,
+
+    /// Discharge a river must carry, in cubic metres a second of rain falling on the ground
+    /// draining into it, on a planet the size of Earth.
+    #[arg(long = "river-threshold", value_name = "discharge", requires = "draw_rivers", default_value_t = 5000.0)]
+    river_threshold: f64,
+
+    /// Fills closed basins on land with lakes where the rain draining into them outpaces
+    /// evaporation, drawn in copies of the sea colors.
+    #[arg(long = "lakes", default_value_t = false)]
+    draw_lakes: bool,
+
     /// Color depends on latitude. Repeats increase intensity.
     #[arg(short = 'c', action = clap::ArgAction::Count)]
     latitude_color: u8,
@@ -238,6 +383,14 @@ struct Args {
     #[arg(short = 'H', default_value_t = false)]
     use_heightfield_format: bool,
 
+    /// Output a tangent-space normal map as PNG, with red pointing east and green pointing north.
+    #[arg(long = "normal-map", default_value_t = false)]
+    use_normal_map_format: bool,
+
+    /// Relief exaggeration applied to slopes in the normal map.
+    #[arg(long = "normal-strength", value_name = "strength", default_value_t = 1.0)]
+    normal_map_strength: f64,
+
     /// Read map from standard input and match new points to map if edge length greater than delta.
     #[arg(short = 'M', value_name = "delta", default_missing_value = "0.0")]
     use_delta_map: Option<f64>,
@@ -261,6 +414,69 @@ struct Args {
     /// Show biomes
     #[arg(short = 'z', default_value_t = false)]
     show_biomes: bool,
+
+    /// Read the biome classification table, names and colors from a file.
+    /// See src/biome_files/default.bio for the format.
+    #[arg(long = "biome-file", value_name = "filename", requires = "show_biomes", verbatim_doc_comment)]
+    biome_filename: Option<String>,
+
+    /// Show Köppen-Geiger climate zones, estimated from seasonal temperature and rainfall
+    #[arg(short = 'k', long = "koppen", default_value_t = false)]
+    show_koppen: bool,
+
+    /// Axial tilt of the planet in degrees, which gives it seasons.
+    #[arg(long = "obliquity", value_name = "degrees", default_value_t = 23.44)]
+    obliquity: f64,
+
+    /// Calculate temperature, rainfall and biomes for a season of a tilted planet:
+    /// annual (the yearly mean), summer or winter (of the northern hemisphere),
+    /// or an orbital phase in degrees from the northern spring equinox.
+    /// Without this the planet is treated as having no axial tilt.
+    #[arg(
+        long = "season",
+        value_name = "season",
+        value_parser = render::climate::Season::from_name,
+        allow_negative_numbers = true,
+        verbatim_doc_comment
+    )]
+    season: Option<render::climate::Season>,
+
+    /// Write layers as separate PNG files named after the output file, from:
+    /// altitude, temperature, rainfall, rain_shadow (16-bit grayscale, 0 outside the map)
+    /// and biome (indexed: 0 outside the map, 1 water, then each biome in table order).
+    #[arg(
+        long = "layers",
+        value_name = "layers",
+        value_delimiter = ',',
+        value_parser = clap::builder::PossibleValuesParser::new(render::layers::LAYER_NAMES),
+        verbatim_doc_comment
+    )]
+    layers: Vec<String>,
+
+    /// Write a JSON file describing each biome's name, color and map coverage.
+    #[arg(long = "biome-info", requires = "land_classes", default_value_t = false)]
+    write_biome_sidecar: bool,
+
+    /// Write a PNG legend of the biome colors and their map coverage.
+    #[arg(long = "biome-legend", requires = "land_classes", default_value_t = false)]
+    write_biome_legend: bool,
+
+    /// Write whole planet statistics: land fraction, altitude histogram and hypsometric curve,
+    /// mean temperature and rainfall, and biome shares, measured by area on the sphere.
+    #[arg(
+        long = "stats",
+        value_name = "format",
+        value_parser = clap::builder::PossibleValuesParser::new(render::stats::STATS_FORMATS)
+    )]
+    stats_format: Option<String>,
+
+    /// Write a JSON file listing each landmass with its area, centroid and bounds.
+    #[arg(long = "landmasses", default_value_t = false)]
+    write_landmass_report: bool,
+
+    /// Write a 16-bit grayscale PNG numbering the landmasses by decreasing area, with 0 for water.
+    #[arg(long = "landmass-labels", default_value_t = false)]
+    write_landmass_labels: bool,
     
     // Specify the randomness precision with Original (o), Normal (n), or High (h).
     #[arg(long = "precision", default_value = "oooo")]
@@ -277,26 +493,188 @@ struct Args {
     ///     c : Conical (conformal)
     ///     M : Mollweide
     ///     S : Sinusoidal (non-functional)
-    ///     i : Icosahedral
+    ///     i : Icosahedral (-L moves the view up or down the net)
+    ///     R : Robinson
+    ///     W : Winkel tripel
+    ///     G : Goode homolosine (interrupted)
+    ///     L : Lambert conformal conic
+    ///     A : Albers equal-area conic
+    ///     T : Transverse Mercator
+    ///     V : Vertical perspective (satellite view)
+    ///     H : HEALPix
     ///
     #[arg(
         short = 'p',
         value_name = "projection",
         default_value_t = String::from("m"),
         verbatim_doc_comment,
-        value_parser = clap::builder::PossibleValuesParser::new(["m", "p", "q", "s", "o", "g", "a", "c", "M", "S", "i"]),
+        value_parser = clap::builder::PossibleValuesParser::new(["m", "p", "q", "s", "o", "g", "a", "c", "M", "S", "i", "R", "W", "G", "L", "A", "T", "V", "H"]),
         hide_possible_values = true,
         hide_default_value = true
     )]
     projection: String,
+
+    /// Lobes of the northern half of the Goode homolosine projection, as alternating
+    /// interruption and central meridians in degrees east of the map's center,
+    /// from -180 to 180.
+    #[arg(
+        long = "goode-north",
+        value_name = "meridians",
+        default_value = projection::goode::NORTH_LOBES,
+        value_parser = projection::goode::GoodeLobes::from_list,
+        allow_hyphen_values = true,
+        verbatim_doc_comment
+    )]
+    goode_north_lobes: projection::goode::GoodeLobes,
+
+    /// Lobes of the southern half of the Goode homolosine projection, in the same form.
+    #[arg(
+        long = "goode-south",
+        value_name = "meridians",
+        default_value = projection::goode::SOUTH_LOBES,
+        value_parser = projection::goode::GoodeLobes::from_list,
+        allow_hyphen_values = true
+    )]
+    goode_south_lobes: projection::goode::GoodeLobes,
+
+    /// Standard parallels of the Lambert and Albers conic projections, in degrees.
+    /// Without this the cone touches the planet at the center latitude,
+    /// kept at least 10 degrees from the equator and the poles.
+    #[arg(
+        long = "parallels",
+        num_args = 2,
+        value_names = ["lat1", "lat2"],
+        allow_negative_numbers = true,
+        verbatim_doc_comment
+    )]
+    standard_parallels: Option<Vec<f64>>,
+
+    /// Central meridian of the Transverse Mercator projection, in degrees.
+    /// Without this it is the longitude of the center.
+    #[arg(
+        long = "central-meridian",
+        value_name = "longitude",
+        allow_negative_numbers = true,
+        verbatim_doc_comment
+    )]
+    central_meridian: Option<f64>,
+
+    /// Scale of the Transverse Mercator projection along its central meridian, such as 0.9996.
+    #[arg(long = "scale-factor", value_name = "factor", default_value_t = 1.0)]
+    scale_factor: f64,
+
+    /// Altitude of the camera of the vertical perspective projection, in planet radii.
+    #[arg(long = "view-altitude", value_name = "radii", default_value_t = 1.0)]
+    view_altitude: f64,
+
+    /// Tilt of the camera of the vertical perspective projection from straight down, in degrees.
+    #[arg(long = "view-tilt", value_name = "degrees", default_value_t = 0.0)]
+    view_tilt: f64,
+
+    /// Direction the camera of the vertical perspective projection tilts towards, in degrees
+    /// clockwise from north.
+    #[arg(
+        long = "view-azimuth",
+        value_name = "degrees",
+        default_value_t = 0.0,
+        allow_negative_numbers = true
+    )]
+    view_azimuth: f64,
+
+    /// Write the altitude at the center of each pixel of a HEALPix grid with this many pixels
+    /// along each side of its base faces, as big-endian 32-bit floats. The HEALPix projection
+    /// also shows the grid's pixels.
+    #[arg(long = "healpix", value_name = "nside")]
+    healpix_nside: Option<usize>,
+
+    /// Order of the HEALPix grid's pixels: ring, or nested which needs a power of two nside.
+    #[arg(
+        long = "healpix-order",
+        value_name = "order",
+        default_value = "ring",
+        value_parser = clap::builder::PossibleValuesParser::new(projection::healpix::HEALPIX_ORDERS)
+    )]
+    healpix_order: String,
+
+    /// Orientation of the icosahedral projection's polyhedron: classic, with vertices at the
+    /// poles, dymaxion, as on Fuller's map, or the latitude, longitude and roll in degrees of
+    /// its first vertex.
+    #[arg(
+        long = "icosahedron",
+        value_name = "orientation",
+        default_value = "classic",
+        value_parser = projection::icosahedral::IcosahedronOrientation::from_name,
+        allow_hyphen_values = true,
+        verbatim_doc_comment
+    )]
+    icosahedron: projection::icosahedral::IcosahedronOrientation,
+
+    /// Draw the edges of the icosahedral projection's faces, black where the net is cut out and
+    /// in the grid color where it is folded.
+    #[arg(long = "face-edges", default_value_t = false)]
+    draw_face_edges: bool,
+
+    /// Re-render using the parameters embedded in a BMP, PNG, PPM or XPM file made by this program.
+    /// Any other options given are applied on top of the stored ones. The stored output file is
+    /// not reused, so give -o to write to a file.
+    #[arg(long = "from-image", value_name = "filename")]
+    from_image: Option<String>,
 }
 
 pub fn get_commandline_footer() -> String {
-    let args = env::args().collect::<Vec<String>>();
+    let args = match COMMAND_LINE.get() {
+        Some(args) => args.clone(),
+        None => env::args().skip(1).collect::<Vec<String>>(),
+    };
+
+    let args = args
+        .iter()
+        .map(|x| {
+            if x.is_empty() || x.contains(char::is_whitespace) {
+                format!("\"{x}\"")
+            } else {
+                x.clone()
+            }
+        })
+        .collect::<Vec<String>>();
+    format!("Command line:\n{}\n", args.join(" "))
+}
 
-    if args.len() > 1 {
-        format!("Command line:\n{}\n", args[1..].join(" "))
-    } else {
-        "Command line:\n\n".into()
+/// Finds the last command line footer in the text and splits it back into arguments
+pub fn parse_commandline_footer(text: &str) -> Option<Vec<String>> {
+    let start = text.rfind("Command line:\n")? + "Command line:\n".len();
+    let line = text[start..].lines().next().unwrap_or("");
+
+    let mut retval = vec![];
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(arg) = current.take() {
+                    retval.push(arg);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
     }
+    if let Some(arg) = current {
+        retval.push(arg);
+    }
+    Some(retval)
+}
+
+#[test]
+fn test_parse_commandline_footer() {
+    let text = "BM\0\0pixels\0Command line:\n-s 0.5 -C \"my colors.col\" -l -130\n";
+    assert_eq!(
+        parse_commandline_footer(text).unwrap(),
+        vec!["-s", "0.5", "-C", "my colors.col", "-l", "-130"]
+    );
+    assert_eq!(parse_commandline_footer("Command line:\n\n").unwrap().len(), 0);
+    assert!(parse_commandline_footer("no footer here").is_none());
 }
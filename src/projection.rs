@@ -1,16 +1,51 @@
+use crate::geometry;
 use crate::geometry::Vertex;
+use crate::projection::albers::Albers;
+use crate::projection::azimuthal::Azimuthal;
+use crate::projection::conical::Conical;
+use crate::projection::gnomonic::Gnomonic;
+use crate::projection::goode::Goode;
+use crate::projection::healpix::Healpix;
+use crate::projection::icosahedral::Icosahedral;
+use crate::projection::lambert::Lambert;
+use crate::projection::mercator::Mercator;
+use crate::projection::mollweide::Mollweide;
+use crate::projection::orthographic::Orthographic;
+use crate::projection::perspective::Perspective;
+use crate::projection::peters::Peters;
+use crate::projection::robinson::Robinson;
+use crate::projection::sinusoidal::Sinusoidal;
+use crate::projection::square::Square;
+use crate::projection::stereographic::Stereographic;
+use crate::projection::transverse_mercator::TransverseMercator;
+use crate::projection::winkel::WinkelTripel;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::PI;
 
+pub mod albers;
 pub mod azimuthal;
+pub mod bounding_box;
 pub mod conical;
 pub mod gnomonic;
+pub mod goode;
+pub mod healpix;
 pub mod icosahedral;
+pub mod lambert;
 pub mod mercator;
 pub mod mollweide;
 pub mod orthographic;
+pub mod perspective;
 pub mod peters;
+pub mod robinson;
 pub mod sinusoidal;
 pub mod square;
 pub mod stereographic;
+pub mod transverse_mercator;
+pub mod winkel;
+
+// shallowest subdivision depth, deep enough for the tetrahedron calc_altitude caches 5 levels up
+const MIN_SUBDIVISION_DEPTH: u8 = 6;
 
 #[derive(Clone)]
 pub enum ProjectionMode {
@@ -25,9 +60,206 @@ pub enum ProjectionMode {
     Mollweide,
     Sinusoidal,
     Icosahedral,
+    Robinson,
+    WinkelTripel,
+    Goode,
+    Lambert,
+    Albers,
+    TransverseMercator,
+    Perspective,
+    Healpix,
+}
+
+/// Creates the projector for a slice of the map, and whether its subdivision depth changes from
+/// row to row
+pub fn create_projector(slice_id: u8, options: &RenderOptions) -> (Box<dyn Projector>, bool) {
+    match options.projection {
+        ProjectionMode::Albers => (Albers::create(slice_id, options), false),
+        ProjectionMode::Azimuthal => (Azimuthal::create(slice_id, options), false),
+        ProjectionMode::Conical => (Conical::create(slice_id, options), false),
+        ProjectionMode::Gnomonic => (Gnomonic::create(slice_id, options), false),
+        ProjectionMode::Goode => (Goode::create(slice_id, options), false),
+        ProjectionMode::Healpix => (Healpix::create(slice_id, options), false),
+        ProjectionMode::Icosahedral => (Icosahedral::create(slice_id, options), false),
+        ProjectionMode::Lambert => (Lambert::create(slice_id, options), false),
+        ProjectionMode::Mercator => (Mercator::create(slice_id, options), true),
+        ProjectionMode::Mollweide => (Mollweide::create(slice_id, options), true),
+        ProjectionMode::Orthographic => (Orthographic::create(slice_id, options), false),
+        ProjectionMode::Perspective => (Perspective::create(slice_id, options), false),
+        ProjectionMode::Peters => (Peters::create(slice_id, options), true),
+        ProjectionMode::Robinson => (Robinson::create(slice_id, options), false),
+        ProjectionMode::Sinusoidal => (Sinusoidal::create(slice_id, options), true),
+        ProjectionMode::Square => (Square::create(slice_id, options), true),
+        ProjectionMode::Stereographic => (Stereographic::create(slice_id, options), false),
+        ProjectionMode::TransverseMercator => (TransverseMercator::create(slice_id, options), false),
+        ProjectionMode::WinkelTripel => (WinkelTripel::create(slice_id, options), false),
+    }
 }
 
 pub trait Projector {
     fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex>;
     fn get_subdivision_depth(&self, h: usize) -> u8;
+    /// Row of the whole image and column where a point on the planet is drawn, which are whole
+    /// numbers at the centers of pixels, or None if the map doesn't show it. Maps that show a
+    /// point more than once give a single copy, on the image where there is one.
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)>;
+}
+
+/// Turns a latitude and longitude relative to the center of the map, in radians, into a point
+/// on the planet
+pub fn vertex_from_lat_long(cp: &LatLong, latitude: f64, longitude: f64) -> Vertex {
+    let (x, y, z) = (
+        longitude.sin() * latitude.cos(),
+        -latitude.sin(),
+        longitude.cos() * latitude.cos(),
+    );
+    geometry::common_vertex_from_point(cp, &x, &y, &z)
+}
+
+/// Latitude and longitude relative to the center of the map, in radians, of a point on the
+/// planet
+pub fn lat_long_from_vertex(cp: &LatLong, p: &Vertex) -> (f64, f64) {
+    let (x, y, z) = geometry::point_from_common_vertex(cp, p);
+    ((-y).clamp(-1.0, 1.0).asin(), x.atan2(z))
+}
+
+/// Brings a longitude difference into -π to π, in radians
+pub fn wrap_longitude(longitude: f64) -> f64 {
+    (longitude + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Column of a point on the maps with straight meridians spaced evenly across the scaled width
+pub fn get_cylindrical_column(cp: &LatLong, p: &Vertex, scaled_width: f64, width: f64) -> f64 {
+    let theta = wrap_longitude((-p.z).atan2(p.x) - cp.longitude + 0.5 * PI);
+    (theta * scaled_width / PI + width) / 2.0
+}
+
+/// Row and column of a position on the maps that put the height of the image between -1 and 1
+/// at a magnification of 1, with y increasing downwards
+pub fn get_plane_pixel(x: f64, y: f64, scaled_height: f64, i_width: i32, i_height: i32) -> (f64, f64) {
+    (
+        (y * scaled_height + i_height as f64) / 2.0,
+        (x * scaled_height + i_width as f64) / 2.0,
+    )
+}
+
+/// Row and column of a position on the maps measured with get_pixel_size, with y increasing
+/// upwards
+pub fn get_map_pixel(x: f64, y: f64, pixel_size: f64, f_width: f64, f_height: f64) -> (f64, f64) {
+    (f_height / 2.0 - y / pixel_size, x / pixel_size + f_width / 2.0)
+}
+
+/// Subdivision depth for pixels spanning the given angle on the planet, in radians, never so
+/// shallow that pixels spanning a radian or more break the altitude cache
+pub fn get_depth_for_pixel_angle(angle: f64) -> u8 {
+    (3 * (2.0 / angle).log2() as u8 + 3).max(MIN_SUBDIVISION_DEPTH)
+}
+
+/// Distance on the map each pixel covers, so the whole map of the given half extents fits the
+/// image at a magnification of 1
+pub fn get_pixel_size(options: &RenderOptions, half_width: f64, half_height: f64) -> f64 {
+    let width = 2.0 * half_width / options.slicing.width as f64;
+    let height = 2.0 * half_height / options.slicing.height as f64;
+    width.max(height) / options.scale
+}
+
+/// Distance from the apex of a cone and angle around it of a position on a conic map, whose
+/// apex is at (0, rho0) and whose cone constant is n
+pub fn get_cone_position(n: f64, rho0: f64, x: f64, y: f64) -> (f64, f64) {
+    let sign = n.signum();
+    let rho = sign * (x * x + (rho0 - y) * (rho0 - y)).sqrt();
+    (rho, (sign * x).atan2(sign * (rho0 - y)))
+}
+
+/// Position on a conic map of a parallel's distance from the apex and a longitude, the inverse
+/// of get_cone_position
+pub fn get_cone_map_position(n: f64, rho0: f64, rho: f64, longitude: f64) -> (f64, f64) {
+    let theta = n * longitude;
+    (rho * theta.sin(), rho0 - rho * theta.cos())
+}
+
+/// Options for a render with the given arguments and the default color file, for testing
+#[cfg(test)]
+pub fn get_test_options<'a>(args: impl IntoIterator<Item = &'a str>) -> RenderOptions {
+    use clap::Parser;
+    let color_file = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("color_files")
+        .join("default.col");
+    let defaults = ["landgen", "-C", color_file.to_str().unwrap()];
+    let args = defaults.map(String::from).into_iter().chain(args.into_iter().map(String::from));
+    crate::Args::parse_from(args).into_options()
+}
+
+#[test]
+fn test_depth_for_pixel_angle() {
+    assert_eq!(get_depth_for_pixel_angle(2.0 / 1024.0), 33);
+    // pixels wider than the planet still get the depth the altitude cache needs
+    assert_eq!(get_depth_for_pixel_angle(4.0), MIN_SUBDIVISION_DEPTH);
+}
+
+#[test]
+fn test_coordinate_to_pixel() {
+    let distance = |a: &Vertex, b: &Vertex| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+
+    for (letter, extra) in [
+        ("m", &[][..]),
+        ("p", &[]),
+        ("q", &[]),
+        ("s", &[]),
+        ("o", &[]),
+        ("g", &[]),
+        ("a", &[]),
+        ("c", &[]),
+        ("M", &[]),
+        ("S", &[]),
+        ("i", &["--icosahedron", "dymaxion"]),
+        ("R", &[]),
+        ("W", &[]),
+        ("G", &[]),
+        ("L", &["--parallels", "30", "60"]),
+        ("A", &["--parallels", "30", "60"]),
+        ("T", &["--central-meridian", "10"]),
+        ("V", &["--view-altitude", "0.5", "--view-tilt", "30"]),
+        ("H", &[]),
+    ] {
+        let args = [
+            "-p", letter, "-w", "121", "-h", "81", "--threads", "3", "-L", "20", "-l", "30", "-m", "1.1",
+        ];
+        let options = get_test_options(args.into_iter().chain(extra.iter().copied()));
+        let projectors = (0..options.slicing.slice_count)
+            .map(|x| create_projector(x, &options).0)
+            .collect::<Vec<Box<dyn Projector>>>();
+        let get_coordinate = |h: usize, w: usize| {
+            let (slice_id, h) = options.slicing.translate_index(h);
+            projectors[slice_id].pixel_to_coordinate(h, w)
+        };
+
+        let mut found = 0;
+        for h in 0..options.slicing.height {
+            for w in 0..options.slicing.width {
+                let Some(p) = get_coordinate(h, w) else { continue };
+                found += 1;
+                let (h1, w1) = projectors[found % projectors.len()]
+                    .coordinate_to_pixel(&p)
+                    .unwrap_or_else(|| panic!("{letter}: no pixel for ({h}, {w})"));
+                if (h1 - h as f64).abs() < 1e-6 && (w1 - w as f64).abs() < 1e-6 {
+                    continue;
+                }
+                // maps that show a point twice may give the other copy, which has to show the same
+                // point to within a pixel
+                let (h1, w1) = (h1.round() as usize, w1.round() as usize);
+                let copy = get_coordinate(h1, w1)
+                    .unwrap_or_else(|| panic!("{letter}: ({h}, {w}) went to ({h1}, {w1})"));
+                let spacing = [(h1, w1 + 1), (h1, w1.wrapping_sub(1)), (h1 + 1, w1), (h1.wrapping_sub(1), w1)]
+                    .into_iter()
+                    .filter(|(h, w)| *h < options.slicing.height && *w < options.slicing.width)
+                    .filter_map(|(h, w)| get_coordinate(h, w))
+                    .map(|x| distance(&x, &copy))
+                    .fold(0.0, f64::max);
+                assert!(distance(&p, &copy) <= spacing, "{letter}: ({h}, {w}) went to ({h1}, {w1})");
+            }
+        }
+        assert!(found > 0, "{letter}: nothing on the map");
+    }
 }
@@ -0,0 +1,90 @@
+use crate::geometry::Vertex;
+use crate::projection::{
+    get_cone_map_position, get_cone_position, get_depth_for_pixel_angle, get_map_pixel, lat_long_from_vertex,
+    vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::PI;
+
+/// Albers equal-area conic with two standard parallels, which are drawn without distortion. The
+/// cone follows the planet's axis, with the center of the map on its central meridian.
+pub struct Albers {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    axis: LatLong,
+    pixel_size: f64,
+    n: f64,
+    c: f64,
+    rho0: f64,
+}
+
+impl Albers {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        let (n, c) = get_cone(options.standard_parallels);
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            axis: LatLong::new_with_trig(0.0, options.center_point.longitude),
+            pixel_size: 2.0 / (options.slicing.height as f64 * options.scale),
+            n,
+            c,
+            rho0: get_radius(n, c, options.center_point.latitude),
+            slicing: options.slicing.clone(),
+        })
+    }
+}
+
+/// Cone constant and the constant that keeps the standard parallels true to length
+fn get_cone((lat1, lat2): (f64, f64)) -> (f64, f64) {
+    let n = (lat1.sin() + lat2.sin()) / 2.0;
+    (n, lat1.cos().powi(2) + 2.0 * n * lat1.sin())
+}
+
+/// Distance of a parallel from the apex of the cone
+fn get_radius(n: f64, c: f64, latitude: f64) -> f64 {
+    (c - 2.0 * n * latitude.sin()).sqrt() / n
+}
+
+impl Projector for Albers {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let x = (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let y = (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size;
+        let (rho, theta) = get_cone_position(self.n, self.rho0, x, y);
+        let longitude = theta / self.n;
+        let latitude_sin = (self.c - rho * rho * self.n * self.n) / (2.0 * self.n);
+        if longitude.abs() > PI || latitude_sin.abs() > 1.0 {
+            return None;
+        }
+        Some(vertex_from_lat_long(&self.axis, latitude_sin.asin(), longitude))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.axis, p);
+        let (x, y) = get_cone_map_position(self.n, self.rho0, get_radius(self.n, self.c, latitude), longitude);
+        (x.is_finite() && y.is_finite())
+            .then(|| get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+    }
+}
+
+#[test]
+fn test_albers_equal_area() {
+    let (n, c) = get_cone((20f64.to_radians(), 50f64.to_radians()));
+    let radius = |latitude: f64| get_radius(n, c, latitude);
+    assert!((n * radius(20f64.to_radians()) / 20f64.to_radians().cos() - 1.0).abs() < 1e-12);
+    assert!((n * radius(50f64.to_radians()) / 50f64.to_radians().cos() - 1.0).abs() < 1e-12);
+
+    // the ring between two parallels covers n / 2π of its band of the sphere
+    let (lat1, lat2) = (-0.3, 0.9);
+    let ring = n * (radius(lat1).powi(2) - radius(lat2).powi(2)) / 2.0 * 2.0 * PI;
+    assert!((ring - 2.0 * PI * (lat2.sin() - lat1.sin())).abs() < 1e-12);
+}
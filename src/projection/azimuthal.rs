@@ -1,5 +1,5 @@
-use crate::geometry::{common_vertex_from_point, Vertex};
-use crate::projection::Projector;
+use crate::geometry::{common_vertex_from_point, point_from_common_vertex, Vertex};
+use crate::projection::{get_plane_pixel, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -46,4 +46,14 @@ impl Projector for Azimuthal {
     fn get_subdivision_depth(&self, _: usize) -> u8 {
         3 * self.scaled_height.log2() as u8 + 6
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (x, y, z) = point_from_common_vertex(&self.cp, p);
+        // the antipode of the center is the whole outline of the map
+        if z <= -1. + 1e-12 {
+            return None;
+        }
+        let zz = ((1. + z) / 2.).sqrt();
+        Some(get_plane_pixel(x / zz, y / zz, self.scaled_height, self.i_width, self.i_height))
+    }
 }
@@ -0,0 +1,155 @@
+use crate::geometry::Vertex;
+use crate::projection::{create_projector, vertex_from_lat_long};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+
+// points sampled along each side of the box, and across its inside, to find its extent
+const SAMPLES: usize = 17;
+
+// rounds of fitting the magnification, for the maps whose extent isn't proportional to it
+const FIT_ITERATIONS: usize = 3;
+
+// the Mercator projection can't reach the poles
+const MERCATOR_LIMIT: f64 = 85.0;
+
+/// Region of the planet to frame, in degrees. West may be greater than east for a box across
+/// the antimeridian.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+impl BoundingBox {
+    /// Parses the west, south, east and north edges in degrees
+    pub fn from_list(list: &str) -> Result<Self, String> {
+        let edges = list
+            .split(',')
+            .map(|x| x.trim().parse::<f64>().map_err(|_| format!("{x} is not a number of degrees")))
+            .collect::<Result<Vec<f64>, String>>()?;
+        let [west, south, east, north] = edges[..] else {
+            return Err("expected west,south,east,north".into());
+        };
+        if [west, east].iter().any(|x| x.abs() > 180.0) || [south, north].iter().any(|x| x.abs() > 90.0) {
+            return Err("longitudes must be from -180 to 180 and latitudes from -90 to 90".into());
+        }
+        if south >= north || west == east {
+            return Err("the box must have south below north and west apart from east".into());
+        }
+        Ok(Self {
+            west,
+            south,
+            east,
+            north,
+        })
+    }
+
+    /// East edge, moved past 180 for a box across the antimeridian
+    fn get_unwrapped_east(&self) -> f64 {
+        if self.east < self.west {
+            self.east + 360.0
+        } else {
+            self.east
+        }
+    }
+
+    /// Latitude and longitude in degrees to center the map on, so the box is in the middle of the
+    /// projection with the given letter
+    pub fn get_center(&self, projection: &str) -> (f64, f64) {
+        let longitude = (self.west + self.get_unwrapped_east()) / 2.0;
+        let (south, north) = (self.south.to_radians(), self.north.to_radians());
+        let latitude = match projection {
+            // halfway between the edges' heights on the map
+            "m" => {
+                let limit = MERCATOR_LIMIT.to_radians();
+                let height = |x: f64| x.clamp(-limit, limit).tan().asinh();
+                ((height(south) + height(north)) / 2.0).sinh().atan().to_degrees()
+            }
+            "p" => ((south.sin() + north.sin()) / 2.0).asin().to_degrees(),
+            _ => (self.south + self.north) / 2.0,
+        };
+        (latitude, if longitude > 180.0 { longitude - 360.0 } else { longitude })
+    }
+
+    /// Points spread over the box
+    fn get_samples(&self) -> Vec<Vertex> {
+        let axis = LatLong::new_with_trig(0.0, 0.0);
+        let east = self.get_unwrapped_east();
+        let step = |from: f64, to: f64, i: usize| {
+            (from + (to - from) * i as f64 / (SAMPLES - 1) as f64).to_radians()
+        };
+        (0..SAMPLES)
+            .flat_map(|i| {
+                (0..SAMPLES).map(move |j| (step(self.south, self.north, i), step(self.west, east, j)))
+            })
+            .map(|(latitude, longitude)| vertex_from_lat_long(&axis, latitude, longitude))
+            .collect()
+    }
+
+    /// Farthest the box reaches from the middle of the image across its height and width, in
+    /// pixels
+    fn get_extent(samples: &[Vertex], options: &RenderOptions) -> Option<(f64, f64)> {
+        let projector = create_projector(0, options).0;
+        let (middle_h, middle_w) = (options.slicing.height as f64 / 2.0, options.slicing.width as f64 / 2.0);
+        samples
+            .iter()
+            .filter_map(|x| projector.coordinate_to_pixel(x))
+            .map(|(h, w)| ((h - middle_h).abs(), (w - middle_w).abs()))
+            .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))
+            .filter(|(h, w)| h.is_finite() && w.is_finite() && (*h > 0.0 || *w > 0.0))
+    }
+
+    /// Sets the magnification so the box fills the image, and first the height of the image so it
+    /// has the box's shape if asked to
+    pub fn fit(&self, options: &mut RenderOptions, fit_aspect: bool) {
+        let samples = self.get_samples();
+        let Some((extent_h, extent_w)) = Self::get_extent(&samples, options) else {
+            eprintln!("The bounding box is not visible in this projection");
+            panic!()
+        };
+        if fit_aspect {
+            // pixels are square, so the shape doesn't depend on the magnification
+            let height = (options.slicing.width as f64 * extent_h / extent_w).round() as usize;
+            options.slicing = Slicing::new(
+                height.max(options.slicing.slice_count as usize),
+                options.slicing.width,
+                options.slicing.slice_count,
+            );
+        }
+        for _ in 0..FIT_ITERATIONS {
+            let Some((extent_h, extent_w)) = Self::get_extent(&samples, options) else {
+                break;
+            };
+            let fill = (extent_h / (options.slicing.height as f64 / 2.0))
+                .max(extent_w / (options.slicing.width as f64 / 2.0));
+            options.scale = (options.scale / fill).clamp(0.1, 100_000.0);
+        }
+    }
+}
+
+#[test]
+fn test_bounding_box() {
+    let bbox = BoundingBox::from_list("170,-10,-170,30").unwrap();
+    assert_eq!(bbox.get_center("o"), (10.0, 180.0));
+    let (latitude, _) = bbox.get_center("m");
+    assert!(latitude > 10.0 && latitude < 11.0);
+    assert_eq!(BoundingBox::from_list("-10,20,10,60").unwrap().get_center("q"), (40.0, 0.0));
+
+    assert!(BoundingBox::from_list("0,0,10").is_err());
+    assert!(BoundingBox::from_list("0,30,10,20").is_err());
+    assert!(BoundingBox::from_list("0,0,190,20").is_err());
+
+    // after fitting, the box reaches the edge of the image in one direction, to within the half
+    // pixel the cylindrical maps snap their center row to
+    for letter in ["m", "q", "o", "L", "R", "i"] {
+        let args = ["-p", letter, "-w", "200", "-h", "100", "--bbox", "-20,30,40,70"];
+        let options = crate::projection::get_test_options(args);
+        let bbox = BoundingBox::from_list("-20,30,40,70").unwrap();
+        let (h, w) = BoundingBox::get_extent(&bbox.get_samples(), &options).unwrap();
+        let fill = (h / 50.0).max(w / 100.0);
+        assert!((fill - 1.0).abs() < 0.01, "{letter}: the box fills {fill} of the image");
+    }
+}
@@ -1,5 +1,5 @@
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{get_plane_pixel, wrap_longitude, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -67,4 +67,16 @@ impl Projector for Conical {
             3 * self.scaled_height.log2() as u8 + 6
         }
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let theta1 = wrap_longitude((-p.z).atan2(p.x) - self.cp.longitude + 0.5 * PI);
+        let s = (p.y.clamp(-1., 1.).asin() / self.k1).sin();
+        let zz = self.c * (1. + s) / (1. - s);
+        if !zz.is_finite() {
+            return None;
+        }
+        let angle = theta1 / self.k1;
+        let (x, y) = (zz.sqrt() * angle.sin(), zz.sqrt() * angle.cos() - self.y2);
+        Some(get_plane_pixel(x, y, self.scaled_height, self.i_width, self.i_height))
+    }
 }
@@ -1,6 +1,6 @@
 use crate::geometry;
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{get_plane_pixel, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -48,4 +48,12 @@ impl Projector for Gnomonic {
             3 * self.scaled_height.log2() as u8 + 6
         }
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (x, y, z) = geometry::point_from_common_vertex(&self.cp, p);
+        if z <= 0. {
+            return None;
+        }
+        Some(get_plane_pixel(x / z, y / z, self.scaled_height, self.i_width, self.i_height))
+    }
 }
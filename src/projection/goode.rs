@@ -0,0 +1,175 @@
+use crate::geometry::Vertex;
+use crate::projection::mollweide::get_auxiliary_angle;
+use crate::projection::{
+    get_depth_for_pixel_angle, get_map_pixel, get_pixel_size, lat_long_from_vertex, vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::{PI, SQRT_2};
+
+// latitude where the sinusoidal and Mollweide projections have parallels of the same length,
+// 40°44'11.8"
+const JOIN_LATITUDE: f64 = 0.710_987_989_993;
+
+// the Mollweide parts are moved towards the equator by this to meet the sinusoidal part
+const MOLLWEIDE_OFFSET: f64 = 0.052_803_527_454_2;
+
+const HALF_HEIGHT: f64 = SQRT_2 - MOLLWEIDE_OFFSET;
+
+// alternating interruption and central meridians of Goode's usual lobes, from west to east
+pub const NORTH_LOBES: &str = "-180,-100,-40,30,180";
+pub const SOUTH_LOBES: &str = "-180,-160,-100,-60,-20,20,80,140,180";
+
+/// Part of the map between two interruptions, which is projected around its own central
+/// meridian. Longitudes are in radians, relative to the center of the map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lobe {
+    pub west: f64,
+    pub central: f64,
+    pub east: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoodeLobes(pub Vec<Lobe>);
+
+impl GoodeLobes {
+    /// Parses alternating interruption and central meridians in degrees, from -180 to 180
+    pub fn from_list(list: &str) -> Result<Self, String> {
+        let meridians = list
+            .split(',')
+            .map(|x| x.trim().parse::<f64>().map_err(|_| format!("{x} is not a number of degrees")))
+            .collect::<Result<Vec<f64>, String>>()?;
+        if meridians.len() < 3 || meridians.len() % 2 == 0 {
+            return Err("expected interruptions at -180 and 180 with a central meridian between each pair".into());
+        }
+        if meridians[0] != -180.0 || meridians[meridians.len() - 1] != 180.0 {
+            return Err("the first and last interruptions must be -180 and 180".into());
+        }
+        if meridians.windows(2).any(|x| x[0] >= x[1]) {
+            return Err("meridians must increase from west to east".into());
+        }
+        Ok(GoodeLobes(
+            meridians
+                .windows(3)
+                .step_by(2)
+                .map(|x| Lobe {
+                    west: x[0].to_radians(),
+                    central: x[1].to_radians(),
+                    east: x[2].to_radians(),
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Goode homolosine, an interrupted equal-area projection that is sinusoidal near the equator
+/// and Mollweide towards the poles. Pixels in the interruptions are outside the map.
+pub struct Goode {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    cp: LatLong,
+    pixel_size: f64,
+    north_lobes: Vec<Lobe>,
+    south_lobes: Vec<Lobe>,
+}
+
+impl Goode {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            pixel_size: get_pixel_size(options, PI, HALF_HEIGHT),
+            slicing: options.slicing.clone(),
+            cp: options.center_point.clone(),
+            north_lobes: options.goode_north_lobes.clone(),
+            south_lobes: options.goode_south_lobes.clone(),
+        })
+    }
+}
+
+/// Latitude at a distance from the equator, and how much the parallel there is shortened
+fn get_parallel(y: f64) -> Option<(f64, f64)> {
+    if y.abs() <= JOIN_LATITUDE {
+        return Some((y, y.cos()));
+    }
+    let theta = ((y.abs() + MOLLWEIDE_OFFSET) / SQRT_2).asin().copysign(y);
+    let latitude = ((2.0 * theta + (2.0 * theta).sin()) / PI).asin();
+    (!latitude.is_nan()).then_some((latitude, 2.0 * SQRT_2 / PI * theta.cos()))
+}
+
+/// Latitude and longitude of a position on the map, unless it is in an interruption
+fn unproject(lobes: &[Lobe], x: f64, y: f64) -> Option<(f64, f64)> {
+    let (latitude, length) = get_parallel(y)?;
+    lobes.iter().find_map(|lobe| {
+        let longitude = lobe.central + (x - lobe.central) / length;
+        (lobe.west..=lobe.east).contains(&longitude).then_some((latitude, longitude))
+    })
+}
+
+/// Position on the map of a latitude and longitude, in radians
+fn project(lobes: &[Lobe], latitude: f64, longitude: f64) -> (f64, f64) {
+    let lobe = lobes.iter().find(|x| longitude <= x.east).unwrap();
+    if latitude.abs() <= JOIN_LATITUDE {
+        return (lobe.central + (longitude - lobe.central) * latitude.cos(), latitude);
+    }
+    let theta = get_auxiliary_angle(latitude);
+    (
+        lobe.central + 2.0 * SQRT_2 / PI * (longitude - lobe.central) * theta.cos(),
+        SQRT_2 * theta.sin() - MOLLWEIDE_OFFSET.copysign(latitude),
+    )
+}
+
+impl Projector for Goode {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let x = (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let y = (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size;
+        let lobes = if y >= 0.0 { &self.north_lobes } else { &self.south_lobes };
+        let (latitude, longitude) = unproject(lobes, x, y)?;
+        Some(vertex_from_lat_long(&self.cp, latitude, longitude))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.cp, p);
+        let lobes = if latitude >= 0.0 { &self.north_lobes } else { &self.south_lobes };
+        let (x, y) = project(lobes, latitude, longitude);
+        Some(get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+    }
+}
+
+#[test]
+fn test_goode_lobes() {
+    let north = GoodeLobes::from_list(NORTH_LOBES).unwrap().0;
+    let south = GoodeLobes::from_list(SOUTH_LOBES).unwrap().0;
+    assert_eq!(north.len(), 2);
+    assert_eq!(south.len(), 4);
+    assert!((south[1].central + 60f64.to_radians()).abs() < 1e-12);
+    assert!(GoodeLobes::from_list("-180,0").is_err());
+    assert!(GoodeLobes::from_list("-180,20,10,30,180").is_err());
+
+    // the parallels of both parts meet at the join, and the whole map is as tall as expected
+    let (_, below) = project(&north, JOIN_LATITUDE - 1e-12, 1.0);
+    let (_, above) = project(&north, JOIN_LATITUDE + 1e-12, 1.0);
+    assert!((below - above).abs() < 1e-9);
+    assert!((project(&north, PI / 2.0, 0.0).1 - HALF_HEIGHT).abs() < 1e-9);
+
+    for (latitude, longitude) in [(0.3, 2.0), (1.2, -0.5), (-0.9, -1.5), (-0.2, 3.0), (0.0, -PI)] {
+        let lobes = if latitude >= 0.0 { &north } else { &south };
+        let (x, y) = project(lobes, latitude, longitude);
+        let (lat, long) = unproject(lobes, x, y).unwrap();
+        assert!((lat - latitude).abs() < 1e-9 && (long - longitude).abs() < 1e-9);
+    }
+
+    // between the southern lobes centered on 160°W and 60°W
+    let (x, y) = project(&south, -1.0, -100f64.to_radians());
+    assert!(unproject(&south, x + 0.05, y).is_none());
+    assert!(unproject(&south, x - 0.05, y).is_some());
+}
@@ -0,0 +1,260 @@
+use crate::geometry::Vertex;
+use crate::projection::{
+    get_depth_for_pixel_angle, get_map_pixel, get_pixel_size, lat_long_from_vertex, vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+pub const HEALPIX_ORDERS: [&str; 2] = ["ring", "nested"];
+
+// ring of the northernmost corner and position around it of each of the twelve base faces
+const FACE_RINGS: [usize; 12] = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+const FACE_COLUMNS: [usize; 12] = [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HealpixOrder {
+    Ring,
+    Nested,
+}
+
+impl HealpixOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ring" => Some(HealpixOrder::Ring),
+            "nested" => Some(HealpixOrder::Nested),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            HealpixOrder::Ring => "ring",
+            HealpixOrder::Nested => "nested",
+        }
+    }
+}
+
+/// Pixel of a HEALPix grid, as a base face and the column and row of the pixel within it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HealpixPixel {
+    pub face: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+impl HealpixPixel {
+    /// Every pixel of a grid, face by face
+    pub fn all(nside: usize) -> impl Iterator<Item = Self> {
+        (0..12).flat_map(move |face| {
+            (0..nside).flat_map(move |y| (0..nside).map(move |x| HealpixPixel { face, x, y }))
+        })
+    }
+
+    /// Pixel containing a latitude and longitude, in radians
+    pub fn find(nside: usize, latitude: f64, longitude: f64) -> Self {
+        let z = latitude.sin();
+        let n = nside as f64;
+        let t = longitude.rem_euclid(2.0 * PI) / FRAC_PI_2;
+        if z.abs() <= 2.0 / 3.0 {
+            let (a, b) = (n * (0.5 + t), n * z * 0.75);
+            let (jp, jm) = ((a - b) as usize, (a + b) as usize);
+            let (face_p, face_m) = (jp / nside, jm / nside);
+            let face = match face_p.cmp(&face_m) {
+                std::cmp::Ordering::Equal => (face_p % 4) | 4,
+                std::cmp::Ordering::Less => face_p % 4,
+                std::cmp::Ordering::Greater => face_m % 4 + 8,
+            };
+            HealpixPixel {
+                face,
+                x: jm % nside,
+                y: nside - jp % nside - 1,
+            }
+        } else {
+            let column = (t as usize).min(3);
+            let tp = t - column as f64;
+            let size = n * (3.0 * (1.0 - z.abs())).sqrt();
+            let jp = ((tp * size) as usize).min(nside - 1);
+            let jm = (((1.0 - tp) * size) as usize).min(nside - 1);
+            if z > 0.0 {
+                HealpixPixel {
+                    face: column,
+                    x: nside - jm - 1,
+                    y: nside - jp - 1,
+                }
+            } else {
+                HealpixPixel {
+                    face: column + 8,
+                    x: jp,
+                    y: jm,
+                }
+            }
+        }
+    }
+
+    /// Ring counted from the north pole, position in the ring counted from 1, the number of
+    /// pixels in each quarter of the ring, and whether its first pixel is centered on the prime meridian
+    fn get_ring_position(&self, nside: usize) -> (usize, usize, usize, bool) {
+        let ring = FACE_RINGS[self.face] * nside - self.x - self.y - 1;
+        let (quarter, shifted) = if ring < nside {
+            (ring, false)
+        } else if ring > 3 * nside {
+            (4 * nside - ring, false)
+        } else {
+            (nside, (ring - nside) % 2 == 1)
+        };
+        let position = (FACE_COLUMNS[self.face] * quarter + self.x + 1 + usize::from(shifted)) as isize
+            - self.y as isize;
+        let position = (position / 2 - 1).rem_euclid(4 * quarter as isize) as usize + 1;
+        (ring, position, quarter, shifted)
+    }
+
+    /// Latitude and longitude of the center of the pixel, in radians
+    pub fn get_center(&self, nside: usize) -> (f64, f64) {
+        let (ring, position, quarter, shifted) = self.get_ring_position(nside);
+        let n = nside as f64;
+        let z = if ring < nside {
+            1.0 - (quarter * quarter) as f64 / (3.0 * n * n)
+        } else if ring > 3 * nside {
+            (quarter * quarter) as f64 / (3.0 * n * n) - 1.0
+        } else {
+            (2.0 * n - ring as f64) / (1.5 * n)
+        };
+        let offset = if shifted { 1.0 } else { 0.5 };
+        (z.asin(), (position as f64 - offset) * FRAC_PI_2 / quarter as f64)
+    }
+
+    /// Index of the pixel in the RING or NESTED numbering
+    pub fn get_index(&self, nside: usize, order: HealpixOrder) -> usize {
+        match order {
+            HealpixOrder::Nested => self.face * nside * nside + interleave(self.x) + 2 * interleave(self.y),
+            HealpixOrder::Ring => {
+                let (ring, position, quarter, _) = self.get_ring_position(nside);
+                let polar_cap = 2 * nside * (nside - 1);
+                if ring < nside {
+                    2 * quarter * (quarter - 1) + position - 1
+                } else if ring > 3 * nside {
+                    12 * nside * nside - 2 * quarter * (quarter + 1) + position - 1
+                } else {
+                    polar_cap + (ring - nside) * 4 * nside + position - 1
+                }
+            }
+        }
+    }
+}
+
+/// Spreads the bits of a number out to the even bits
+fn interleave(x: usize) -> usize {
+    (0..usize::BITS as usize / 2).map(|i| (x >> i & 1) << (2 * i)).sum()
+}
+
+/// The HEALPix projection, an equal-area layout of the twelve faces of the grid with the
+/// equatorial band cylindrical and a triangle over each face around the poles. The map is
+/// centered on the prime meridian so the faces line up with the grid. When a grid size is given
+/// each pixel shows the altitude at the center of its grid pixel.
+pub struct Healpix {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    axis: LatLong,
+    pixel_size: f64,
+    nside: Option<usize>,
+}
+
+impl Healpix {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            axis: LatLong::new_with_trig(0.0, 0.0),
+            pixel_size: get_pixel_size(options, PI, FRAC_PI_2),
+            slicing: options.slicing.clone(),
+            nside: options.healpix_nside,
+        })
+    }
+}
+
+/// Position on the map of a latitude and longitude, in radians
+fn project(latitude: f64, longitude: f64) -> (f64, f64) {
+    let z = latitude.sin();
+    if z.abs() <= 2.0 / 3.0 {
+        return (longitude, 3.0 * PI / 8.0 * z);
+    }
+    let column = ((longitude + PI) / FRAC_PI_2).floor().clamp(0.0, 3.0);
+    let center = -PI + (2.0 * column + 1.0) * FRAC_PI_4;
+    let t = (3.0 * (1.0 - z.abs())).sqrt();
+    (center + (longitude - center) * t, (FRAC_PI_4 * (2.0 - t)).copysign(z))
+}
+
+/// Latitude and longitude of a position on the map, unless it is between the polar triangles
+fn unproject(x: f64, y: f64) -> Option<(f64, f64)> {
+    if x.abs() > PI || y.abs() > FRAC_PI_2 {
+        return None;
+    }
+    if y.abs() <= FRAC_PI_4 {
+        return Some(((8.0 * y / (3.0 * PI)).asin(), x));
+    }
+    let column = ((x + PI) / FRAC_PI_2).floor().min(3.0);
+    let center = -PI + (2.0 * column + 1.0) * FRAC_PI_4;
+    let t = 2.0 - 4.0 * y.abs() / PI;
+    if (x - center).abs() > t * FRAC_PI_4 {
+        return None;
+    }
+    let longitude = if t > 0.0 { center + (x - center) / t } else { center };
+    Some(((1.0 - t * t / 3.0).asin().copysign(y), longitude))
+}
+
+impl Projector for Healpix {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let x = (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let y = (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size;
+        let (mut latitude, mut longitude) = unproject(x, y)?;
+        if let Some(nside) = self.nside {
+            (latitude, longitude) = HealpixPixel::find(nside, latitude, longitude).get_center(nside);
+        }
+        Some(vertex_from_lat_long(&self.axis, latitude, longitude))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size)
+    }
+
+    /// Where the point itself falls, even when the grid pixel around it is drawn at its center
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.axis, p);
+        let (x, y) = project(latitude, longitude);
+        Some(get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+    }
+}
+
+#[test]
+fn test_healpix_grid() {
+    // the first pixel of the coarsest grid
+    let (latitude, longitude) = HealpixPixel { face: 0, x: 0, y: 0 }.get_center(1);
+    assert!((latitude - (2.0f64 / 3.0).asin()).abs() < 1e-12 && (longitude - FRAC_PI_4).abs() < 1e-12);
+
+    for nside in [1, 2, 4, 8] {
+        let pixels = HealpixPixel::all(nside).collect::<Vec<HealpixPixel>>();
+        let mut by_ring = vec![None; pixels.len()];
+        let mut nested = vec![false; pixels.len()];
+        for pixel in &pixels {
+            let (latitude, longitude) = pixel.get_center(nside);
+            assert_eq!(HealpixPixel::find(nside, latitude, longitude), *pixel);
+            by_ring[pixel.get_index(nside, HealpixOrder::Ring)] = Some(latitude);
+            nested[pixel.get_index(nside, HealpixOrder::Nested)] = true;
+        }
+        // both numberings cover every pixel, and RING runs from north to south
+        assert!(nested.iter().all(|x| *x));
+        let latitudes = by_ring.into_iter().collect::<Option<Vec<f64>>>().unwrap();
+        assert!(latitudes.windows(2).all(|x| x[0] >= x[1]));
+    }
+
+    // the map has a gap between the polar triangles
+    assert!(unproject(0.0, 1.2).is_none());
+    let (latitude, longitude) = unproject(FRAC_PI_4, 1.2).unwrap();
+    assert!(latitude > 0.0 && (longitude - FRAC_PI_4).abs() < 1e-12);
+}
@@ -1,101 +1,310 @@
-use crate::geometry;
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{get_depth_for_pixel_angle, get_map_pixel, Projector};
 use crate::render::slicing::Slicing;
-use crate::render::RenderOptions;
-use crate::terrain::LatLong;
+use crate::render::{RenderOptions, RenderState};
+use std::f64::consts::PI;
 
+// height of a net triangle with sides of 1
+const TRIANGLE_HEIGHT: f64 = 0.866_025_403_784_438_6;
+
+// extent of the net, with the northern ring of vertices along y = 0
+const NET_WIDTH: f64 = 5.5;
+const NET_TOP: f64 = TRIANGLE_HEIGHT;
+const NET_BOTTOM: f64 = -2.0 * TRIANGLE_HEIGHT;
+
+// length of an edge of an icosahedron inside the unit sphere
+const EDGE_LENGTH: f64 = 1.051_462_224_238_267_2;
+
+// a vertex of Fuller's Dymaxion icosahedron, and a neighbor of it, as latitude and longitude
+const DYMAXION_VERTEX: (f64, f64) = (64.7, 10.536_199);
+const DYMAXION_NEIGHBOR: (f64, f64) = (2.300_882, -5.245_39);
+
+type NetPoint = (f64, f64);
+
+// longitude the classic net is centered on, before turning to the center of the map
+const CLASSIC_ROLL: f64 = -198.0;
+
+/// Where the icosahedron's first vertex is, as latitude and longitude in degrees, and how far
+/// it is turned about that vertex
+#[derive(Clone, Debug, PartialEq)]
+pub struct IcosahedronOrientation {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub roll: f64,
+}
+
+impl IcosahedronOrientation {
+    /// Takes "classic", with vertices at the poles, "dymaxion", with Fuller's vertices that
+    /// nearly all fall at sea, or a latitude, longitude and roll in degrees
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "classic" => Ok(Self {
+                latitude: 90.0,
+                longitude: 0.0,
+                roll: CLASSIC_ROLL,
+            }),
+            "dymaxion" => {
+                let (latitude, longitude) = DYMAXION_VERTEX;
+                let mut orientation = Self {
+                    latitude,
+                    longitude,
+                    roll: 0.0,
+                };
+                // turn so the neighbor lands on the first vertex of the northern ring
+                let neighbor = orientation.unrotate(from_lat_long(DYMAXION_NEIGHBOR.0, DYMAXION_NEIGHBOR.1));
+                orientation.roll = neighbor[0].atan2(neighbor[2]).to_degrees();
+                Ok(orientation)
+            }
+            _ => {
+                let values = name
+                    .split(',')
+                    .map(|x| x.trim().parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| format!("{name} is not classic, dymaxion or latitude,longitude,roll"))?;
+                match values[..] {
+                    [latitude, longitude, roll] => Ok(Self {
+                        latitude,
+                        longitude,
+                        roll,
+                    }),
+                    _ => Err(format!("{name} is not classic, dymaxion or latitude,longitude,roll")),
+                }
+            }
+        }
+    }
+
+    /// Moves a point from the frame with the first vertex at the north pole to the planet
+    fn rotate(&self, [x, y, z]: [f64; 3], longitude: f64) -> [f64; 3] {
+        let (sin, cos) = self.roll.to_radians().sin_cos();
+        let (x, z) = (x * cos + z * sin, z * cos - x * sin);
+        let (sin, cos) = (90.0 - self.latitude).to_radians().sin_cos();
+        let (y, z) = (y * cos + z * sin, z * cos - y * sin);
+        let (sin, cos) = (self.longitude.to_radians() + longitude).sin_cos();
+        [x * cos + z * sin, y, z * cos - x * sin]
+    }
+
+    fn unrotate(&self, [x, y, z]: [f64; 3]) -> [f64; 3] {
+        let (sin, cos) = self.longitude.to_radians().sin_cos();
+        let (x, z) = (x * cos - z * sin, z * cos + x * sin);
+        let (sin, cos) = (90.0 - self.latitude).to_radians().sin_cos();
+        let (y, z) = (y * cos - z * sin, z * cos + y * sin);
+        let (sin, cos) = self.roll.to_radians().sin_cos();
+        [x * cos - z * sin, y, z * cos + x * sin]
+    }
+}
+
+/// Point on the unit sphere at a latitude and longitude in degrees, in the frame of Vertex
+fn from_lat_long(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+    [
+        longitude.sin() * latitude.cos(),
+        -latitude.sin(),
+        longitude.cos() * latitude.cos(),
+    ]
+}
+
+/// Vertices of the icosahedron with the first at the north pole: a northern ring every 72° of
+/// longitude at arctan(1/2), a southern ring between them and the south pole
+fn get_vertices() -> [[f64; 3]; 12] {
+    let ring = 0.5f64.atan().to_degrees();
+    std::array::from_fn(|i| match i {
+        0 => from_lat_long(90.0, 0.0),
+        1..=5 => from_lat_long(ring, 72.0 * (i - 1) as f64),
+        6..=10 => from_lat_long(-ring, 36.0 + 72.0 * (i - 6) as f64),
+        _ => from_lat_long(-90.0, 0.0),
+    })
+}
+
+/// Faces laid out as five northern triangles over a band of ten and five southern triangles
+/// below, with each corner given as a vertex of the icosahedron and its position on the net
+fn get_net() -> Vec<[(usize, f64, f64); 3]> {
+    let t = TRIANGLE_HEIGHT;
+    (0..5)
+        .flat_map(|k| {
+            let (north, next_north) = (1 + k, 1 + (k + 1) % 5);
+            let (south, next_south) = (6 + k, 6 + (k + 1) % 5);
+            let x = k as f64;
+            [
+                [(north, x, 0.0), (next_north, x + 1.0, 0.0), (0, x + 0.5, t)],
+                [(north, x, 0.0), (next_north, x + 1.0, 0.0), (south, x + 0.5, -t)],
+                [(south, x + 0.5, -t), (next_south, x + 1.5, -t), (next_north, x + 1.0, 0.0)],
+                [(south, x + 0.5, -t), (next_south, x + 1.5, -t), (11, x + 1.0, -2.0 * t)],
+            ]
+        })
+        .collect()
+}
+
+/// Weights of the corners of a triangle that give a position, which are all positive inside it
+fn get_barycentric(triangle: &[(usize, f64, f64); 3], x: f64, y: f64) -> [f64; 3] {
+    let [(_, x0, y0), (_, x1, y1), (_, x2, y2)] = *triangle;
+    let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+    let a = ((x1 - x) * (y2 - y) - (x2 - x) * (y1 - y)) / area;
+    let b = ((x2 - x) * (y0 - y) - (x0 - x) * (y2 - y)) / area;
+    [a, b, 1.0 - a - b]
+}
+
+/// Weights of the vertices of a face that blend to the point where the direction of p meets the
+/// face, which are all positive if p is over the face
+fn get_face_weights(vertices: [[f64; 3]; 3], p: [f64; 3]) -> [f64; 3] {
+    let determinant = |a: [f64; 3], b: [f64; 3], c: [f64; 3]| {
+        a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0]) + a[2] * (b[0] * c[1] - b[1] * c[0])
+    };
+    let [a, b, c] = vertices;
+    let weights = [determinant(p, b, c), determinant(a, p, c), determinant(a, b, p)];
+    let total = weights.iter().sum::<f64>();
+    weights.map(|x| x / total)
+}
+
+/// Distance from a position to a line segment
+fn get_segment_distance((x0, y0): NetPoint, (x1, y1): NetPoint, x: f64, y: f64) -> f64 {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let t = (((x - x0) * dx + (y - y0) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    (x - x0 - t * dx).hypot(y - y0 - t * dy)
+}
+
+/// Icosahedral projection, an unfolded net of an icosahedron with each face a gnomonic
+/// projection of the part of the planet under it. The polyhedron can be oriented like Fuller's
+/// Dymaxion map or turned freely, and the center longitude turns the planet inside it. The
+/// center latitude moves the middle of the image up or down the net, half its height for each 90°.
 pub struct Icosahedral {
     slicing: Slicing,
     slice_id: u8,
-    i_height: i32,
-    i_width: i32,
-    cp: LatLong,
-    scaled_height: f64,
-    scaled_width: f64,
-    sq3: f64,
-    l1: f64,
-    l2: f64,
-    s: f64,
+    f_height: f64,
+    f_width: f64,
+    pixel_size: f64,
+    center_y: f64,
+    vertices: [[f64; 3]; 12],
+    net: Vec<[(usize, f64, f64); 3]>,
 }
 
 impl Icosahedral {
     pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
-        Box::new(Self {
+        Box::new(Self::new(slice_id, options))
+    }
+
+    fn new(slice_id: u8, options: &RenderOptions) -> Self {
+        let width = NET_WIDTH / options.slicing.width as f64;
+        let height = (NET_TOP - NET_BOTTOM) / options.slicing.height as f64;
+        Self {
             slice_id,
-            i_height: options.slicing.height as i32,
-            i_width: options.slicing.width as i32,
-            scaled_height: options.slicing.height as f64 * options.scale,
-            scaled_width: options.slicing.width as f64 * options.scale,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            pixel_size: width.max(height) / options.scale,
+            center_y: (NET_TOP + NET_BOTTOM) / 2.0
+                + options.center_point.latitude / PI * (NET_TOP - NET_BOTTOM),
+            vertices: get_vertices()
+                .map(|x| options.icosahedron.rotate(x, options.center_point.longitude)),
+            net: get_net(),
             slicing: options.slicing.clone(),
-            cp: options.center_point.clone(),
-            sq3: 3.0_f64.sqrt(),
-            l1: 10.812317,  /* theoretically 10.9715145571469; */
-            l2: -52.622632, /* theoretically -48.3100310579607; */
-            s: 55.6,
-        })
+        }
+    }
+
+    /// Position on the net of a pixel
+    fn get_net_position(&self, real_h: usize, w: usize) -> (f64, f64) {
+        (
+            NET_WIDTH / 2.0 + (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size,
+            self.center_y + (self.f_height - 2.0 * real_h as f64) / 2.0 * self.pixel_size,
+        )
     }
 }
 
 impl Projector for Icosahedral {
     fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
-        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as i32;
-        let x0 = 198.0 * (2 * w as i32 - self.i_width) as f64 / self.scaled_width - 36.0;
-        let y0 = 198.0 * (2 * real_h - self.i_height) as f64 / self.scaled_width
-            - self.cp.latitude / 1.0_f64.to_radians();
-
-        let y3 = y0 / self.sq3;
-        let (lat, long) = match y3 {
-            ..-18.0 => match (x0 - y3, x0 + y3) {
-                (..144.0, 36.0..) => (-self.l2, 90.0),
-                (..72.0, -36.0..) => (-self.l2, 18.0),
-                (..0.0, -108.0..) => (-self.l2, -54.0),
-                (..-72.0, -180.0..) => (-self.l2, -126.0),
-                (..-144.0, -252.0..) => (-self.l2, -198.0),
-                (_, _) => (500.0, 0.),
-            },
-            -18.0..=18.0 => match (x0 - y3, x0 + y3) {
-                (..144.0, 108.0..) => (-self.l1, 126.0),
-                (..72.0, 36.0..) => (-self.l1, 54.0),
-                (..0.0, -36.0..) => (-self.l1, -18.0),
-                (..-72.0, -108.0..) => (-self.l1, -90.0),
-                (..-144.0, -180.0..) => (-self.l1, -162.0),
-                (72.0.., ..108.0) => (self.l1, 90.0),
-                (0.0.., ..36.0) => (self.l1, 18.0),
-                (-72.0.., ..-36.0) => (self.l1, -54.0),
-                (-144.0.., ..-108.0) => (self.l1, -126.0),
-                (-216.0.., ..-180.0) => (self.l1, -198.0),
-                (_, _) => (500.0, 0.0),
-            },
-            18.0.. => match (x0 + y3, x0 - y3) {
-                (..180.0, 72.0..) => (self.l2, 126.0),
-                (..108.0, 0.0..) => (self.l2, 54.0),
-                (..36.0, -72.0..) => (self.l2, -18.0),
-                (..-36.0, -144.0..) => (self.l2, -90.0),
-                (..-108.0, -216.0..) => (self.l2, -162.0),
-                (_, _) => (500., 0.),
-            },
-            _ => (500.0, 0.0),
-        };
-
-        if lat <= 400.0 {
-            let mut x = (x0 - long) / self.s;
-            let mut y = (y0 + lat) / self.s;
-
-            let point =
-                LatLong::new_with_trig(lat.to_radians(), long.to_radians() - self.cp.longitude);
-
-            let zz = (1.0 / (1.0 + x * x + y * y)).sqrt();
-            x *= zz;
-            y *= zz;
-            let z = (1.0 - x * x - y * y).sqrt();
-            Some(geometry::common_vertex_from_point(&point, &x, &y, &z))
-        } else {
-            None
-        }
+        let (x, y) = self.get_net_position(self.slicing.get_absolute_height(self.slice_id, h), w);
+        self.net.iter().find_map(|triangle| {
+            let weights = get_barycentric(triangle, x, y);
+            if weights.iter().any(|x| *x < 0.0) {
+                return None;
+            }
+            let [x, y, z] = std::array::from_fn(|i| {
+                (0..3).map(|j| weights[j] * self.vertices[triangle[j].0][i]).sum::<f64>()
+            });
+            let length = (x * x + y * y + z * z).sqrt();
+            Some(Vertex::from_point(x / length, y / length, z / length))
+        })
     }
 
     fn get_subdivision_depth(&self, _: usize) -> u8 {
-        3 * self.scaled_height.log2() as u8 + 6
+        get_depth_for_pixel_angle(self.pixel_size * EDGE_LENGTH)
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        self.net.iter().find_map(|triangle| {
+            let weights = get_face_weights(triangle.map(|x| self.vertices[x.0]), [p.x, p.y, p.z]);
+            // the direction of p also passes through the face opposite
+            let facing = (0..3).map(|i| self.vertices[triangle[0].0][i] * [p.x, p.y, p.z][i]).sum::<f64>() > 0.0;
+            if !facing || weights.iter().any(|x| *x < -1e-12) {
+                return None;
+            }
+            let x = (0..3).map(|j| weights[j] * triangle[j].1).sum::<f64>() - NET_WIDTH / 2.0;
+            let y = (0..3).map(|j| weights[j] * triangle[j].2).sum::<f64>() - self.center_y;
+            Some(get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+        })
+    }
+}
+
+/// Draws the edges of the net's faces, in black where the net is cut out and in the grid color
+/// where it is folded
+pub fn draw_face_edges(state: &RenderState) {
+    let options = &state.options;
+    let mut canvas = state.canvas.write().unwrap();
+    if canvas.iter().all(|x| x.is_empty()) {
+        return;
+    }
+    let projector = Icosahedral::new(0, options);
+    let mut edges: Vec<(NetPoint, NetPoint, bool)> = vec![];
+    for triangle in &projector.net {
+        for i in 0..3 {
+            let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+            let (a, b) = ((a.1, a.2), (b.1, b.2));
+            match edges.iter_mut().find(|x| (x.0 == a && x.1 == b) || (x.0 == b && x.1 == a)) {
+                Some(edge) => edge.2 = true,
+                None => edges.push((a, b, false)),
+            }
+        }
+    }
+
+    for ahi in 0..options.slicing.height {
+        let (vi, hi) = options.slicing.translate_index(ahi);
+        for w in 0..options.slicing.width {
+            let (x, y) = projector.get_net_position(ahi, w);
+            for (a, b, folded) in &edges {
+                if get_segment_distance(*a, *b, x, y) <= projector.pixel_size / 2.0 {
+                    canvas[vi][hi][w] = if *folded {
+                        options.color_table.grid
+                    } else {
+                        options.color_table.black
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_icosahedral_net() {
+    let orientation = IcosahedronOrientation::from_name("dymaxion").unwrap();
+    let vertices = get_vertices().map(|x| orientation.rotate(x, 0.0));
+    let distance = |a: [f64; 3], b: [f64; 3]| (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f64>().sqrt();
+    assert!(distance(vertices[0], from_lat_long(DYMAXION_VERTEX.0, DYMAXION_VERTEX.1)) < 1e-12);
+    assert!(distance(vertices[1], from_lat_long(DYMAXION_NEIGHBOR.0, DYMAXION_NEIGHBOR.1)) < 1e-6);
+
+    // every face is equilateral, and each corner on the net is always the same vertex
+    let net = get_net();
+    assert_eq!(net.len(), 20);
+    for triangle in &net {
+        for i in 0..3 {
+            let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+            assert!((distance(vertices[a.0], vertices[b.0]) - EDGE_LENGTH).abs() < 1e-12);
+            assert!(((a.1 - b.1).hypot(a.2 - b.2) - 1.0).abs() < 1e-12);
+            for other in net.iter().flatten() {
+                if (other.1 - a.1).abs() < 1e-12 && (other.2 - a.2).abs() < 1e-12 {
+                    assert_eq!(other.0, a.0);
+                }
+            }
+        }
+    }
+
+    assert!(IcosahedronOrientation::from_name("10,20,30").is_ok());
+    assert!(IcosahedronOrientation::from_name("10,20").is_err());
 }
@@ -0,0 +1,96 @@
+use crate::geometry::Vertex;
+use crate::projection::{
+    get_cone_map_position, get_cone_position, get_depth_for_pixel_angle, get_map_pixel, lat_long_from_vertex,
+    vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// Lambert conformal conic with two standard parallels, which are drawn without distortion. The
+/// cone follows the planet's axis, with the center of the map on its central meridian.
+pub struct Lambert {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    axis: LatLong,
+    pixel_size: f64,
+    n: f64,
+    f: f64,
+    rho0: f64,
+}
+
+impl Lambert {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        let (n, f) = get_cone(options.standard_parallels);
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            axis: LatLong::new_with_trig(0.0, options.center_point.longitude),
+            pixel_size: 2.0 / (options.slicing.height as f64 * options.scale),
+            n,
+            f,
+            rho0: get_radius(n, f, options.center_point.latitude),
+            slicing: options.slicing.clone(),
+        })
+    }
+}
+
+/// Cone constant and the scale that keeps the standard parallels true to length
+fn get_cone((lat1, lat2): (f64, f64)) -> (f64, f64) {
+    let n = if (lat1 - lat2).abs() < 1e-10 {
+        lat1.sin()
+    } else {
+        (lat1.cos() / lat2.cos()).ln() / ((FRAC_PI_4 + lat2 / 2.0).tan() / (FRAC_PI_4 + lat1 / 2.0).tan()).ln()
+    };
+    (n, lat1.cos() * (FRAC_PI_4 + lat1 / 2.0).tan().powf(n) / n)
+}
+
+/// Distance of a parallel from the apex of the cone
+fn get_radius(n: f64, f: f64, latitude: f64) -> f64 {
+    f / (FRAC_PI_4 + latitude / 2.0).tan().powf(n)
+}
+
+impl Projector for Lambert {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let x = (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let y = (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size;
+        let (rho, theta) = get_cone_position(self.n, self.rho0, x, y);
+        let longitude = theta / self.n;
+        if longitude.abs() > PI {
+            return None;
+        }
+        let latitude = 2.0 * (self.f / rho).powf(1.0 / self.n).atan() - FRAC_PI_2;
+        Some(vertex_from_lat_long(&self.axis, latitude, longitude))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.axis, p);
+        let (x, y) = get_cone_map_position(self.n, self.rho0, get_radius(self.n, self.f, latitude), longitude);
+        (x.is_finite() && y.is_finite())
+            .then(|| get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+    }
+}
+
+#[test]
+fn test_lambert_standard_parallels() {
+    let parallels = (30f64.to_radians(), 60f64.to_radians());
+    let (n, f) = get_cone(parallels);
+    // the scale along a parallel is 1 on both standard parallels and more between them
+    let scale = |latitude: f64| n * get_radius(n, f, latitude) / latitude.cos();
+    assert!((scale(parallels.0) - 1.0).abs() < 1e-12);
+    assert!((scale(parallels.1) - 1.0).abs() < 1e-12);
+    assert!(scale(45f64.to_radians()) < 1.0);
+
+    // a single standard parallel gives the tangent cone
+    let (n, f) = get_cone((-0.7, -0.7));
+    assert!((n * get_radius(n, f, -0.7) / 0.7f64.cos() - 1.0).abs() < 1e-12);
+}
@@ -3,7 +3,7 @@ use crate::render::RenderOptions;
 use crate::terrain::LatLong;
 use std::f64::consts::PI;
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{get_cylindrical_column, Projector};
 
 pub struct Mercator {
     slicing: Slicing,
@@ -64,4 +64,12 @@ impl Projector for Mercator {
         let scale1 = self.scaled_width / (self.f_height * cos2 * PI);
         3 * (scale1 * self.f_height).log2() as u8 + 3
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let h = self.k as f64 + (p.y.atanh() * self.scaled_width / PI + self.f_height) / 2.;
+        if !h.is_finite() {
+            return None;
+        }
+        Some((h, get_cylindrical_column(&self.cp, p, self.scaled_width, self.f_width)))
+    }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{lat_long_from_vertex, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -30,6 +30,20 @@ impl Mollweide {
     }
 }
 
+/// Auxiliary angle of the Mollweide projection at a latitude, in radians, solving
+/// 2θ + sin 2θ = π sin(latitude) by Newton's method
+pub fn get_auxiliary_angle(latitude: f64) -> f64 {
+    let mut theta = latitude;
+    for _ in 0..50 {
+        let error = 2.0 * theta + (2.0 * theta).sin() - PI * latitude.sin();
+        if error.abs() < 1e-14 {
+            break;
+        }
+        theta -= error / (2.0 + 2.0 * (2.0 * theta).cos());
+    }
+    theta
+}
+
 impl Projector for Mollweide {
     fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
         let real_h = self.slicing.get_absolute_height(self.slice_id, h) as i32;
@@ -62,4 +76,14 @@ impl Projector for Mollweide {
         let scale1 = self.scaled_width / (self.f_height * cos2 * PI);
         3 * (scale1 * self.f_height).log2() as u8 + 3
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.cp, p);
+        // rows run south, so the angle is measured from the latitude's mirror image
+        let theta = get_auxiliary_angle(-latitude);
+        Some((
+            (theta.sin() * self.scaled_width / 2. + self.f_height) / 2.,
+            (longitude * self.scaled_width * theta.cos() / PI + self.i_width as f64) / 2.,
+        ))
+    }
 }
@@ -0,0 +1,137 @@
+use crate::geometry;
+use crate::geometry::Vertex;
+use crate::projection::{get_depth_for_pixel_angle, get_plane_pixel, Projector};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+
+/// Vertical perspective, the view of a camera at some altitude above the center of the map. The
+/// camera looks straight down unless tilted towards the horizon. Pixels whose rays miss the
+/// planet are outside the map, and the planet itself hides everything beyond the horizon.
+pub struct Perspective {
+    slicing: Slicing,
+    slice_id: u8,
+    i_height: i32,
+    i_width: i32,
+    cp: LatLong,
+    scaled_height: f64,
+    camera: Camera,
+}
+
+impl Perspective {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        Box::new(Self {
+            slice_id,
+            i_height: options.slicing.height as i32,
+            i_width: options.slicing.width as i32,
+            scaled_height: options.slicing.height as f64 * options.scale,
+            slicing: options.slicing.clone(),
+            cp: options.center_point.clone(),
+            camera: Camera::new(options.view_altitude, options.view_tilt, options.view_azimuth),
+        })
+    }
+}
+
+/// Camera in the frame used by the orthographic projection: x to the east, y to the south and z
+/// up from the center of the map, with the planet's radius as the unit
+struct Camera {
+    distance: f64,
+    right: [f64; 3],
+    down: [f64; 3],
+    forward: [f64; 3],
+    // tangent of the angle between the view axis and the horizon without tilt, so the whole
+    // visible disc fits the height of the image at a magnification of 1
+    spread: f64,
+}
+
+impl Camera {
+    /// Takes the altitude in planet radii, and the tilt from straight down and the azimuth it
+    /// tilts towards from north, in radians
+    fn new(altitude: f64, tilt: f64, azimuth: f64) -> Self {
+        let distance = 1.0 + altitude;
+        let up = [azimuth.sin(), -azimuth.cos(), 0.0];
+        let forward = [up[0] * tilt.sin(), up[1] * tilt.sin(), -tilt.cos()];
+        Self {
+            distance,
+            right: [azimuth.cos(), azimuth.sin(), 0.0],
+            down: [-up[0] * tilt.cos(), -up[1] * tilt.cos(), -tilt.sin()],
+            forward,
+            spread: 1.0 / (distance * distance - 1.0).sqrt(),
+        }
+    }
+
+    /// Nearest point of the planet seen through a position on the image, which runs from -1 to
+    /// 1 across its height
+    fn intersect(&self, x: f64, y: f64) -> Option<[f64; 3]> {
+        let ray: [f64; 3] = std::array::from_fn(|i| {
+            self.forward[i] + self.spread * (x * self.right[i] + y * self.down[i])
+        });
+        let length = ray.iter().map(|x| x * x).sum::<f64>();
+        let along = self.distance * ray[2];
+        let discriminant = along * along - length * (self.distance * self.distance - 1.0);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let k = (-along - discriminant.sqrt()) / length;
+        (k > 0.0).then(|| [k * ray[0], k * ray[1], self.distance + k * ray[2]])
+    }
+
+    /// Position on the image of a point on the planet, unless it is hidden behind the horizon or
+    /// the camera
+    fn project(&self, [x, y, z]: [f64; 3]) -> Option<(f64, f64)> {
+        if z * self.distance < 1.0 - 1e-12 {
+            return None;
+        }
+        let ray = [x, y, z - self.distance];
+        let dot = |axis: &[f64; 3]| (0..3).map(|i| ray[i] * axis[i]).sum::<f64>();
+        let along = dot(&self.forward);
+        (along > 0.0).then(|| {
+            (
+                dot(&self.right) / (self.spread * along),
+                dot(&self.down) / (self.spread * along),
+            )
+        })
+    }
+}
+
+impl Projector for Perspective {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as i32;
+        let x = (2 * w as i32 - self.i_width) as f64 / self.scaled_height;
+        let y = (2 * real_h - self.i_height) as f64 / self.scaled_height;
+        let [x, y, z] = self.camera.intersect(x, y)?;
+        Some(geometry::common_vertex_from_point(&self.cp, &x, &y, &z))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        // pixels are smallest straight below the camera
+        let altitude = self.camera.distance - 1.0;
+        get_depth_for_pixel_angle(2.0 * altitude * self.camera.spread / self.scaled_height)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (x, y, z) = geometry::point_from_common_vertex(&self.cp, p);
+        let (x, y) = self.camera.project([x, y, z])?;
+        Some(get_plane_pixel(x, y, self.scaled_height, self.i_width, self.i_height))
+    }
+}
+
+#[test]
+fn test_perspective_camera() {
+    let camera = Camera::new(0.5, 0.0, 0.0);
+    let nadir = camera.intersect(0.0, 0.0).unwrap();
+    assert!(nadir[0].abs() < 1e-12 && nadir[1].abs() < 1e-12 && (nadir[2] - 1.0).abs() < 1e-12);
+    // the horizon is at the edge of the disc, and the planet hides the far side
+    assert!(camera.intersect(0.999, 0.0).unwrap()[2] > 0.0);
+    assert!(camera.intersect(0.0, -1.001).is_none());
+
+    // from far away it approaches the orthographic view
+    let far = Camera::new(1e6, 0.0, 0.0).intersect(0.3, 0.4).unwrap();
+    assert!((far[0] - 0.3).abs() < 1e-5 && (far[1] - 0.4).abs() < 1e-5);
+
+    // tilting towards the north moves the center of the image north, which is -y
+    let tilted = Camera::new(0.5, 0.3, 0.0).intersect(0.0, 0.0).unwrap();
+    assert!(tilted[0].abs() < 1e-12 && tilted[1] < 0.0);
+    let east = Camera::new(0.5, 0.3, std::f64::consts::FRAC_PI_2).intersect(0.0, 0.0).unwrap();
+    assert!(east[0] > 0.0 && east[1].abs() < 1e-12);
+}
@@ -1,5 +1,5 @@
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{get_cylindrical_column, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -60,4 +60,14 @@ impl Projector for Peters {
         let scale1 = self.scaled_width / (self.f_height * cos2 * PI);
         (scale1 * self.f_height).log2() as u8 * 3 + 3
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        if p.y.abs() >= 1. {
+            return None;
+        }
+        Some((
+            self.k as f64 + (2. * p.y * self.scaled_width / PI + self.f_height) / 2.,
+            get_cylindrical_column(&self.cp, p, self.scaled_width, self.f_width),
+        ))
+    }
 }
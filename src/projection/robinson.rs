@@ -0,0 +1,99 @@
+use crate::geometry::Vertex;
+use crate::projection::{
+    get_depth_for_pixel_angle, get_map_pixel, get_pixel_size, lat_long_from_vertex, vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::PI;
+
+// length of each parallel and its distance from the equator, every 5 degrees of latitude
+const PARALLEL_LENGTHS: [f64; 19] = [
+    1.0000, 0.9986, 0.9954, 0.9900, 0.9822, 0.9730, 0.9600, 0.9427, 0.9216, 0.8962, 0.8679, 0.8350,
+    0.7986, 0.7597, 0.7186, 0.6732, 0.6213, 0.5722, 0.5322,
+];
+const PARALLEL_DISTANCES: [f64; 19] = [
+    0.0000, 0.0620, 0.1240, 0.1860, 0.2480, 0.3100, 0.3720, 0.4340, 0.4958, 0.5571, 0.6176, 0.6769,
+    0.7346, 0.7903, 0.8435, 0.8936, 0.9394, 0.9761, 1.0000,
+];
+const X_SCALE: f64 = 0.8487;
+const Y_SCALE: f64 = 1.3523;
+const TABLE_STEP: f64 = 5.0 * PI / 180.0;
+
+pub struct Robinson {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    cp: LatLong,
+    pixel_size: f64,
+}
+
+impl Robinson {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            pixel_size: get_pixel_size(options, X_SCALE * PI, Y_SCALE),
+            slicing: options.slicing.clone(),
+            cp: options.center_point.clone(),
+        })
+    }
+
+    fn get_latitude(&self, h: usize) -> Option<f64> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let y = (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size / Y_SCALE;
+        get_latitude(y.abs()).map(|x| x.copysign(y))
+    }
+}
+
+/// Length of the parallel at a latitude, interpolated from the table
+fn get_parallel_length(latitude: f64) -> f64 {
+    let position = (latitude.abs() / TABLE_STEP).min(18.0);
+    let i = (position as usize).min(17);
+    let t = position - i as f64;
+    PARALLEL_LENGTHS[i] * (1.0 - t) + PARALLEL_LENGTHS[i + 1] * t
+}
+
+/// Distance of the parallel at a latitude from the equator, interpolated from the table
+fn get_distance(latitude: f64) -> f64 {
+    let position = (latitude.abs() / TABLE_STEP).min(18.0);
+    let i = (position as usize).min(17);
+    let t = position - i as f64;
+    PARALLEL_DISTANCES[i] * (1.0 - t) + PARALLEL_DISTANCES[i + 1] * t
+}
+
+/// Latitude of the parallel at a distance from the equator, the inverse of interpolating the table
+fn get_latitude(distance: f64) -> Option<f64> {
+    if distance > 1.0 {
+        return None;
+    }
+    let i = PARALLEL_DISTANCES.iter().rposition(|x| *x <= distance)?.min(17);
+    let t = (distance - PARALLEL_DISTANCES[i]) / (PARALLEL_DISTANCES[i + 1] - PARALLEL_DISTANCES[i]);
+    Some((i as f64 + t) * TABLE_STEP)
+}
+
+impl Projector for Robinson {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let latitude = self.get_latitude(h)?;
+        let x = (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let longitude = x / (X_SCALE * get_parallel_length(latitude));
+        if longitude.abs() <= PI {
+            Some(vertex_from_lat_long(&self.cp, latitude, longitude))
+        } else {
+            None
+        }
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.cp, p);
+        let x = X_SCALE * get_parallel_length(latitude) * longitude;
+        let y = Y_SCALE * get_distance(latitude).copysign(latitude);
+        Some(get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+    }
+}
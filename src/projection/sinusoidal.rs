@@ -1,5 +1,5 @@
 use crate::geometry::Vertex;
-use crate::projection::Projector;
+use crate::projection::{wrap_longitude, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -64,4 +64,24 @@ impl Projector for Sinusoidal {
         let scale1 = self.scaled_width / (self.f_height * y.cos() * PI);
         (scale1 * self.f_height).log2() as u8 * 3 + 3
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        if p.y.abs() >= 1. {
+            return None;
+        }
+        let h = self.k as f64 + (p.y.asin() * self.scaled_width / PI + self.f_height) / 2.;
+        // theta1 grows 1 + 1 / cos2 times as fast as theta2 across a row, so a row can show a
+        // point twice; take the copy on the image with theta1 nearest 0
+        let cos2 = (1. - p.y * p.y).sqrt();
+        let theta = (-p.z).atan2(p.x) - self.cp.longitude + 0.5 * PI + PI * self.f_width / self.scaled_width;
+        let theta1 = wrap_longitude(theta - PI / 12.) / (1. + cos2);
+        let step = 2. * PI / (1. + cos2);
+        let copies = [theta1, theta1 - step, theta1 + step]
+            .into_iter()
+            .filter(|x| x.abs() <= PI)
+            .map(|x| (x * cos2 + PI / 12.) * self.scaled_width / (2. * PI))
+            .collect::<Vec<f64>>();
+        let on_image = |w: &&f64| (-0.5..self.f_width - 0.5).contains(*w);
+        copies.iter().find(on_image).or(copies.first()).map(|w| (h, *w))
+    }
 }
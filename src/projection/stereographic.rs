@@ -1,5 +1,5 @@
-use crate::geometry::{common_vertex_from_point, Vertex};
-use crate::projection::Projector;
+use crate::geometry::{common_vertex_from_point, point_from_common_vertex, Vertex};
+use crate::projection::{get_plane_pixel, Projector};
 use crate::render::slicing::Slicing;
 use crate::render::RenderOptions;
 use crate::terrain::LatLong;
@@ -49,4 +49,14 @@ impl Projector for Stereographic {
             self.scaled_height.log2() as u8 * 3 + 6
         }
     }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (x, y, z) = point_from_common_vertex(&self.cp, p);
+        // the antipode of the center is infinitely far away
+        if z <= -1. + 1e-12 {
+            return None;
+        }
+        let zz = 2. / (1. + z);
+        Some(get_plane_pixel(x * zz, y * zz, self.scaled_height, self.i_width, self.i_height))
+    }
 }
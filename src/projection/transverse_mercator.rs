@@ -0,0 +1,96 @@
+use crate::geometry::Vertex;
+use crate::projection::{
+    get_depth_for_pixel_angle, get_map_pixel, lat_long_from_vertex, vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+
+/// Transverse Mercator, which wraps the cylinder around a central meridian instead of the
+/// equator. It is conformal and its scale is the scale factor along the central meridian,
+/// growing away from it, so it suits regions that are long from north to south.
+pub struct TransverseMercator {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    axis: LatLong,
+    pixel_size: f64,
+    scale_factor: f64,
+    x0: f64,
+    y0: f64,
+}
+
+impl TransverseMercator {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        let cp = &options.center_point;
+        let (x0, y0) = project(
+            cp.latitude,
+            cp.longitude - options.central_meridian,
+            options.scale_factor,
+        );
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            axis: LatLong::new_with_trig(0.0, options.central_meridian),
+            pixel_size: 2.0 / (options.slicing.height as f64 * options.scale),
+            scale_factor: options.scale_factor,
+            x0,
+            y0,
+            slicing: options.slicing.clone(),
+        })
+    }
+}
+
+/// Position on the map of a latitude and a longitude from the central meridian, in radians
+fn project(latitude: f64, longitude: f64, scale_factor: f64) -> (f64, f64) {
+    let b = latitude.cos() * longitude.sin();
+    (
+        scale_factor * b.atanh(),
+        scale_factor * latitude.tan().atan2(longitude.cos()),
+    )
+}
+
+/// Latitude and longitude from the central meridian of a position on the map
+fn unproject(x: f64, y: f64, scale_factor: f64) -> (f64, f64) {
+    let (x, y) = (x / scale_factor, y / scale_factor);
+    ((y.sin() / x.cosh()).asin(), x.sinh().atan2(y.cos()))
+}
+
+impl Projector for TransverseMercator {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let x = self.x0 + (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let y = self.y0 + (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size;
+        let (latitude, longitude) = unproject(x, y, self.scale_factor);
+        Some(vertex_from_lat_long(&self.axis, latitude, longitude))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size / self.scale_factor)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.axis, p);
+        let (x, y) = project(latitude, longitude, self.scale_factor);
+        // the two points on the equator 90° from the central meridian are infinitely far away
+        if !x.is_finite() {
+            return None;
+        }
+        Some(get_map_pixel(x - self.x0, y - self.y0, self.pixel_size, self.f_width, self.f_height))
+    }
+}
+
+#[test]
+fn test_transverse_mercator() {
+    for (latitude, longitude) in [(0.0, 0.0), (0.7, 0.3), (-1.3, -1.0), (0.2, 2.5), (-0.4, -3.0)] {
+        let (x, y) = project(latitude, longitude, 0.9996);
+        let (lat, long) = unproject(x, y, 0.9996);
+        assert!((lat - latitude).abs() < 1e-12 && (long - longitude).abs() < 1e-12);
+    }
+
+    // distances along the central meridian are shrunk by the scale factor
+    let (x, y) = project(0.5, 0.0, 0.9996);
+    assert!(x.abs() < 1e-15 && (y - 0.5 * 0.9996).abs() < 1e-15);
+}
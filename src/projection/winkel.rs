@@ -0,0 +1,120 @@
+use crate::geometry::Vertex;
+use crate::projection::{
+    get_depth_for_pixel_angle, get_map_pixel, get_pixel_size, lat_long_from_vertex, vertex_from_lat_long, Projector,
+};
+use crate::render::slicing::Slicing;
+use crate::render::RenderOptions;
+use crate::terrain::LatLong;
+use std::f64::consts::{FRAC_2_PI, FRAC_PI_2, PI};
+
+const HALF_WIDTH: f64 = 1.0 + FRAC_PI_2;
+const HALF_HEIGHT: f64 = FRAC_PI_2;
+
+// Newton's method for the inverse, with the step used to estimate its derivatives
+const MAX_ITERATIONS: usize = 25;
+const TOLERANCE: f64 = 1e-10;
+const DERIVATIVE_STEP: f64 = 1e-7;
+
+/// Winkel tripel, the mean of the equirectangular projection with standard parallels at
+/// arccos(2/π) and the Aitoff projection. It has no closed form inverse, so each pixel is solved
+/// for iteratively.
+pub struct WinkelTripel {
+    slicing: Slicing,
+    slice_id: u8,
+    f_height: f64,
+    f_width: f64,
+    cp: LatLong,
+    pixel_size: f64,
+}
+
+impl WinkelTripel {
+    pub fn create(slice_id: u8, options: &RenderOptions) -> Box<dyn Projector> {
+        Box::new(Self {
+            slice_id,
+            f_height: options.slicing.height as f64,
+            f_width: options.slicing.width as f64,
+            pixel_size: get_pixel_size(options, HALF_WIDTH, HALF_HEIGHT),
+            slicing: options.slicing.clone(),
+            cp: options.center_point.clone(),
+        })
+    }
+}
+
+/// Position on the map of a latitude and longitude, in radians
+fn project(latitude: f64, longitude: f64) -> (f64, f64) {
+    let alpha = (latitude.cos() * (longitude / 2.0).cos()).acos();
+    let sinc = if alpha < 1e-12 { 1.0 } else { alpha.sin() / alpha };
+    (
+        0.5 * (longitude * FRAC_2_PI + 2.0 * latitude.cos() * (longitude / 2.0).sin() / sinc),
+        0.5 * (latitude + latitude.sin() / sinc),
+    )
+}
+
+/// Latitude and longitude of a position on the map, if it is on the map
+fn unproject(x: f64, y: f64) -> Option<(f64, f64)> {
+    let mut latitude = y.clamp(-FRAC_PI_2, FRAC_PI_2);
+    let mut longitude = x / (0.5 + 1.0 / PI);
+
+    for _ in 0..MAX_ITERATIONS {
+        let (px, py) = project(latitude, longitude);
+        let (ex, ey) = (px - x, py - y);
+        if ex.abs() < TOLERANCE && ey.abs() < TOLERANCE {
+            return (longitude.abs() <= PI + TOLERANCE).then_some((latitude, longitude));
+        }
+
+        let d = DERIVATIVE_STEP;
+        let (x_north, y_north) = project(latitude + d, longitude);
+        let (x_south, y_south) = project(latitude - d, longitude);
+        let (x_east, y_east) = project(latitude, longitude + d);
+        let (x_west, y_west) = project(latitude, longitude - d);
+        let (dx_dlat, dy_dlat) = ((x_north - x_south) / (2.0 * d), (y_north - y_south) / (2.0 * d));
+        let (dx_dlong, dy_dlong) = ((x_east - x_west) / (2.0 * d), (y_east - y_west) / (2.0 * d));
+        let determinant = dx_dlat * dy_dlong - dx_dlong * dy_dlat;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        latitude -= (ex * dy_dlong - ey * dx_dlong) / determinant;
+        longitude -= (ey * dx_dlat - ex * dy_dlat) / determinant;
+        latitude = latitude.clamp(-FRAC_PI_2, FRAC_PI_2);
+    }
+    None
+}
+
+impl Projector for WinkelTripel {
+    fn pixel_to_coordinate(&self, h: usize, w: usize) -> Option<Vertex> {
+        let real_h = self.slicing.get_absolute_height(self.slice_id, h) as f64;
+        let x = (2.0 * w as f64 - self.f_width) / 2.0 * self.pixel_size;
+        let y = (self.f_height - 2.0 * real_h) / 2.0 * self.pixel_size;
+        if x.abs() > HALF_WIDTH || y.abs() > HALF_HEIGHT {
+            return None;
+        }
+        let (latitude, longitude) = unproject(x, y)?;
+        Some(vertex_from_lat_long(&self.cp, latitude, longitude))
+    }
+
+    fn get_subdivision_depth(&self, _: usize) -> u8 {
+        get_depth_for_pixel_angle(self.pixel_size)
+    }
+
+    fn coordinate_to_pixel(&self, p: &Vertex) -> Option<(f64, f64)> {
+        let (latitude, longitude) = lat_long_from_vertex(&self.cp, p);
+        let (x, y) = project(latitude, longitude);
+        Some(get_map_pixel(x, y, self.pixel_size, self.f_width, self.f_height))
+    }
+}
+
+#[test]
+fn test_winkel_tripel_inverse() {
+    assert!((project(0.0, PI).0 - HALF_WIDTH).abs() < 1e-12);
+    assert!((project(FRAC_PI_2, 0.0).1 - HALF_HEIGHT).abs() < 1e-12);
+
+    for (latitude, longitude) in [(0.0, 0.0), (0.5, 1.0), (-1.2, -2.5), (1.5, 3.0), (-0.3, PI)] {
+        let (x, y) = project(latitude, longitude);
+        let (lat, long) = unproject(x, y).unwrap();
+        assert!((lat - latitude).abs() < 1e-8 && (long - longitude).abs() < 1e-8);
+    }
+
+    // beyond the outline at the antimeridian
+    let (x, y) = project(0.8, PI);
+    assert!(unproject(x + 0.05, y).is_none());
+}
@@ -3,33 +3,41 @@ use crate::file::bitmap::validate_size;
 use crate::file::{write_file, ColorMode, FileType};
 use crate::geometry::Tetra;
 use crate::math::{RenderSeeds, SeedGenerator};
-use crate::projection::azimuthal::Azimuthal;
-use crate::projection::conical::Conical;
-use crate::projection::gnomonic::Gnomonic;
-use crate::projection::icosahedral::Icosahedral;
-use crate::projection::mercator::Mercator;
-use crate::projection::mollweide::Mollweide;
-use crate::projection::orthographic::Orthographic;
-use crate::projection::peters::Peters;
-use crate::projection::sinusoidal::Sinusoidal;
-use crate::projection::square::Square;
-use crate::projection::stereographic::Stereographic;
-use crate::projection::ProjectionMode;
-use crate::render::color::render_pixel;
-use crate::terrain::LatLong;
+use crate::projection::goode::Lobe;
+use crate::projection::healpix::HealpixOrder;
+use crate::projection::icosahedral;
+use crate::projection::icosahedral::IcosahedronOrientation;
+use crate::projection::{create_projector, ProjectionMode};
+use crate::render::color::{color_pixel, render_pixel, PixelSample};
+use crate::render::climate::Season;
+use crate::render::hydrology::River;
+use crate::render::landmass::SurfacePoint;
+use crate::render::stats::StatsFormat;
+use crate::terrain::koppen::KOPPEN_ZONES;
+use crate::terrain::{BiomeTable, LatLong};
 use crate::util::Vec2D;
 use crate::Args;
 use chrono::Utc;
 use gridlines::GridLines;
+use layers::Layer;
 use slicing::Slicing;
 use std::f64::consts::PI;
 use std::sync::{Arc, RwLock};
 use std::thread;
 
 pub mod altitude;
+pub mod climate;
 pub mod color;
+pub mod erosion;
 pub mod gridlines;
+pub mod hydrology;
+pub mod landmass;
+pub mod layers;
 pub mod slicing;
+pub mod stats;
+
+// closest in degrees the default standard parallel of a conic projection comes to the equator or a pole
+const MIN_CONE_LATITUDE: f64 = 10.0;
 
 #[derive(Clone)]
 pub struct RenderOptions {
@@ -41,9 +49,17 @@ pub struct RenderOptions {
     pub output_file: Option<String>,
     pub filetypes: Vec<FileType>,
     pub generate_heightfield: bool,
+    pub generate_normal_map: bool,
+    pub normal_map_strength: f64,
     pub center_point: LatLong,
     pub gridsize: LatLong,
     pub initial_altitude: f64,
+    pub land_fraction: Option<f64>,
+    pub erosion_iterations: u32,
+    pub erosion_strength: f64,
+    pub draw_rivers: bool,
+    pub river_threshold: f64,
+    pub draw_lakes: bool,
     pub altitude_color: u8,
     pub use_nonlinear_altitude_scaling: bool,
     pub make_wrinkly_map: bool,
@@ -56,9 +72,33 @@ pub struct RenderOptions {
     pub delta_map: Option<f64>,
     pub map_rotation: LatLong,
     pub show_biomes: bool,
+    pub biome_table: BiomeTable,
+    pub show_koppen: bool,
+    pub obliquity: f64,
+    pub season: Option<Season>,
     pub projection: ProjectionMode,
+    pub goode_north_lobes: Vec<Lobe>,
+    pub goode_south_lobes: Vec<Lobe>,
+    pub standard_parallels: (f64, f64),
+    pub central_meridian: f64,
+    pub scale_factor: f64,
+    pub view_altitude: f64,
+    pub view_tilt: f64,
+    pub view_azimuth: f64,
+    pub healpix_nside: Option<usize>,
+    pub healpix_order: HealpixOrder,
+    pub icosahedron: IcosahedronOrientation,
+    pub draw_face_edges: bool,
     pub use_temperature: bool,
     pub calculate_rainfall: bool,
+    pub calculate_rain_shadow: bool,
+    pub layers: Vec<Layer>,
+    pub write_biome_sidecar: bool,
+    pub write_biome_legend: bool,
+    pub stats_format: Option<StatsFormat>,
+    pub find_landmasses: bool,
+    pub write_landmass_report: bool,
+    pub write_landmass_labels: bool,
     pub use_latitude_coloring: bool,
     pub latitude_color_intensity: u8,
     pub shading_level: u8,
@@ -86,12 +126,24 @@ impl RenderOptions {
         if args.use_bmp_format {
             retval.push(FileType::bmp);
         }
+        if args.use_normal_map_format {
+            retval.push(FileType::normalmap);
+        }
         retval
     }
+
+    /// Post-processing of the altitudes needs the whole map before any pixel is colored
+    pub fn defers_coloring(&self) -> bool {
+        self.erosion_iterations > 0 || self.draw_rivers || self.draw_lakes
+    }
 }
 
 impl Args {
     pub fn into_options(mut self) -> RenderOptions {
+        let bounding_box = self.bounding_box.take();
+        if let Some(bbox) = &bounding_box {
+            (self.latitude, self.longitude) = bbox.get_center(&self.projection);
+        }
         self.latitude = self.latitude.clamp(-90.0, 90.0);
         while self.longitude < -180.0 {
             self.longitude += 360.0;
@@ -113,22 +165,84 @@ impl Args {
         }
 
         let seed_gen = SeedGenerator::new(&self.precision);
+        let layers = self
+            .layers
+            .iter()
+            .filter_map(|x| Layer::from_name(x))
+            .collect::<Vec<Layer>>();
+
+        let biome_table = match &self.biome_filename {
+            Some(filename) => BiomeTable::from_file(filename),
+            None => BiomeTable::default(),
+        };
+        let land_colors = if self.show_koppen {
+            KOPPEN_ZONES
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (i, x.color.clone()))
+                .collect()
+        } else if self.show_biomes {
+            biome_table.get_land_colors()
+        } else {
+            vec![]
+        };
+
+        let mut color_table = build_color_data(&self.color_filename, &land_colors);
+        if self.draw_rivers {
+            color_table.add_river_color();
+        }
+        if self.draw_lakes {
+            color_table.add_lake_colors();
+        }
+
+        let standard_parallels = match &self.standard_parallels {
+            Some(x) => (x[0].to_radians(), x[1].to_radians()),
+            None => {
+                // a cone touching at the equator is a cylinder, and one at a pole is a plane
+                let latitude = self.latitude.abs().clamp(MIN_CONE_LATITUDE, 90.0 - MIN_CONE_LATITUDE);
+                let latitude = if self.latitude < 0.0 { -latitude } else { latitude }.to_radians();
+                (latitude, latitude)
+            }
+        };
+        if matches!(self.projection.as_str(), "L" | "A")
+            && ((standard_parallels.0 + standard_parallels.1).abs() < 1e-6
+                || standard_parallels.0.abs().max(standard_parallels.1.abs()) >= PI / 2.0)
+        {
+            eprintln!("Standard parallels of a conic projection must be off the poles and not mirror each other across the equator");
+            panic!()
+        }
+
+        let healpix_order = HealpixOrder::from_name(&self.healpix_order).unwrap();
+        if let Some(nside) = self.healpix_nside {
+            if nside == 0 || (healpix_order == HealpixOrder::Nested && !nside.is_power_of_two()) {
+                eprintln!("HEALPix grid size must be positive, and a power of two for nested order");
+                panic!()
+            }
+        }
 
-        RenderOptions {
+        let mut options = RenderOptions {
             seeds: seed_gen.generate(self.seed),
             seed_gen,
             slicing: Slicing::new(self.height, self.width, self.render_threads),
             scale: self.magnification.clamp(0.1, 100_000.0),
-            color_table: build_color_data(&self.color_filename, self.show_biomes),
+            color_table,
             output_file: self.output_file.clone(),
             filetypes: RenderOptions::get_filetypes(&self),
             generate_heightfield: self.use_heightfield_format,
+            generate_normal_map: self.use_normal_map_format,
+            normal_map_strength: self.normal_map_strength,
             center_point: LatLong::new_with_trig(
                 self.latitude.to_radians(),
                 self.longitude.to_radians(),
             ),
             gridsize: LatLong::new(self.latitude_gridsize, self.longitude_gridsize),
             initial_altitude: self.initial_altitude,
+            land_fraction: self.land_fraction.map(|x| x.clamp(0.0, 1.0)),
+            erosion_iterations: self.erosion_iterations,
+            erosion_strength: self.erosion_strength.max(0.0),
+            draw_rivers: self.draw_rivers,
+            river_threshold: self.river_threshold,
+            draw_lakes: self.draw_lakes,
             altitude_color: self.latitude_color,
             use_nonlinear_altitude_scaling: self.use_nonlinear_altitude_scaling,
             make_wrinkly_map: self.make_wrinkly_map,
@@ -144,6 +258,10 @@ impl Args {
                 -self.map_rotation[1].to_radians(),
             ),
             show_biomes: self.show_biomes,
+            biome_table,
+            show_koppen: self.show_koppen,
+            obliquity: self.obliquity.to_radians(),
+            season: self.season,
             projection: match self.projection.as_str() {
                 "m" => {
                     if self.latitude.to_radians().abs() >= PI - 1E-10 {
@@ -170,10 +288,45 @@ impl Args {
                 "M" => ProjectionMode::Mollweide,
                 "S" => ProjectionMode::Sinusoidal,
                 "i" => ProjectionMode::Icosahedral,
+                "R" => ProjectionMode::Robinson,
+                "W" => ProjectionMode::WinkelTripel,
+                "G" => ProjectionMode::Goode,
+                "L" => ProjectionMode::Lambert,
+                "A" => ProjectionMode::Albers,
+                "T" => ProjectionMode::TransverseMercator,
+                "V" => ProjectionMode::Perspective,
+                "H" => ProjectionMode::Healpix,
                 _ => panic!(""),
             },
+            goode_north_lobes: self.goode_north_lobes.0,
+            goode_south_lobes: self.goode_south_lobes.0,
+            standard_parallels,
+            central_meridian: self.central_meridian.unwrap_or(self.longitude).to_radians(),
+            scale_factor: self.scale_factor.clamp(0.1, 10.0),
+            view_altitude: self.view_altitude.max(1e-4),
+            view_tilt: self.view_tilt.clamp(0.0, 89.0).to_radians(),
+            view_azimuth: self.view_azimuth.to_radians(),
+            healpix_nside: self.healpix_nside,
+            healpix_order,
+            icosahedron: self.icosahedron,
+            draw_face_edges: self.draw_face_edges,
             use_temperature: self.use_temperature,
             calculate_rainfall: self.calculate_rainfall,
+            calculate_rain_shadow: self.calculate_rainfall
+                || self.show_biomes
+                || self.show_koppen
+                || self.draw_rivers
+                || self.draw_lakes
+                || layers
+                    .iter()
+                    .any(|x| matches!(x, Layer::Rainfall | Layer::RainShadow | Layer::Biome)),
+            layers,
+            write_biome_sidecar: self.write_biome_sidecar,
+            write_biome_legend: self.write_biome_legend,
+            stats_format: self.stats_format.as_deref().and_then(StatsFormat::from_name),
+            find_landmasses: self.write_landmass_report || self.write_landmass_labels,
+            write_landmass_report: self.write_landmass_report,
+            write_landmass_labels: self.write_landmass_labels,
             use_latitude_coloring: self.latitude_color > 0,
             latitude_color_intensity: self.latitude_color,
             shading_level: if self.draw_daylight {
@@ -190,7 +343,11 @@ impl Args {
             alt_diff_power: if self.make_wrinkly_map { 0.75 } else { 1.0 },
             distance_weight: self.distance_variation,
             distance_power: 0.47,
+        };
+        if let Some(bbox) = bounding_box {
+            bbox.fit(&mut options, self.fit_bbox_aspect);
         }
+        options
     }
 }
 
@@ -199,6 +356,13 @@ pub struct RenderState {
     pub canvas: RwLock<Vec<Vec2D<u16>>>,
     pub heightfield: RwLock<Vec<Vec2D<i32>>>,
     pub shading: RwLock<Vec<Vec2D<u8>>>,
+    pub normal_map: RwLock<Vec<Vec2D<[u8; 3]>>>,
+    pub layers: RwLock<Vec<Vec<Vec2D<f32>>>>,
+    pub surface: RwLock<Vec<Vec2D<Option<SurfacePoint>>>>,
+    pub samples: RwLock<Vec<Vec2D<Option<PixelSample>>>>,
+    pub rivers: RwLock<Vec<River>>,
+    /// Pixels of each biome code or one past each Köppen zone, with water counted under 0
+    pub biome_counts: RwLock<[u64; 256]>,
     pub grid_lines: GridLines,
 }
 
@@ -217,12 +381,23 @@ impl RenderState {
             } else {
                 vec![]
             }),
+            normal_map: RwLock::new(if options.generate_normal_map {
+                vec![vec![]; options.slicing.slice_count as usize]
+            } else {
+                vec![]
+            }),
+            layers: RwLock::new(vec![vec![]; options.slicing.slice_count as usize]),
+            surface: RwLock::new(vec![vec![]; options.slicing.slice_count as usize]),
+            samples: RwLock::new(vec![vec![]; options.slicing.slice_count as usize]),
+            rivers: RwLock::new(vec![]),
+            biome_counts: RwLock::new([0; 256]),
             grid_lines: GridLines::new(0, 0),
         }
     }
 
+    /// Shading varies the brightness of each color, so shaded renders are never monochrome
     pub fn get_color_mode(&self) -> ColorMode {
-        if self.options.color_table.is_monochrome() {
+        if self.options.shading_level == 0 && self.options.color_table.is_monochrome() {
             ColorMode::Monochrome
         } else {
             ColorMode::Color
@@ -235,12 +410,18 @@ pub struct ThreadState {
     pub canvas: Vec2D<u16>,
     pub heightfield: Vec2D<i32>,
     pub shading: Vec2D<u8>,
+    pub normal_map: Vec2D<[u8; 3]>,
+    pub layers: Vec<Vec2D<f32>>,
+    pub surface: Vec2D<Option<SurfacePoint>>,
+    pub samples: Vec2D<Option<PixelSample>>,
+    pub biome_counts: [u64; 256],
     pub search_map: [[i32; 30]; 60],
     pub base_tetra: Tetra,
     pub cached_tetra: Tetra,
     pub starting_subdivision_depth: u8,
     pub rain_shadow: f64,
     pub shade: u8,
+    pub normal: [u8; 3],
 }
 
 impl ThreadState {
@@ -250,12 +431,18 @@ impl ThreadState {
             canvas: gen_canvas(id, &options),
             heightfield: gen_heightfield(id, &options),
             shading: gen_shading(id, &options),
+            normal_map: gen_normal_map(id, &options),
+            layers: gen_layers(id, &options),
+            surface: gen_surface(id, &options),
+            samples: gen_samples(id, &options),
+            biome_counts: [0; 256],
             search_map: [[0; 30]; 60],
             base_tetra: crate::geometry::create_base_tetra(&options),
             cached_tetra: crate::geometry::create_base_tetra(&options),
             starting_subdivision_depth: 0,
             rain_shadow: 0.0,
             shade: 0,
+            normal: [128, 128, 255],
         }
     }
 }
@@ -289,8 +476,46 @@ fn gen_shading(id: u8, options: &RenderOptions) -> Vec2D<u8> {
     }
 }
 
+fn gen_normal_map(id: u8, options: &RenderOptions) -> Vec2D<[u8; 3]> {
+    if options.generate_normal_map {
+        vec![vec![[128, 128, 255]; options.slicing.width]; options.slicing.get_slice_height(id)]
+    } else {
+        vec![]
+    }
+}
+
+// pixels outside of the projection are None
+fn gen_surface(id: u8, options: &RenderOptions) -> Vec2D<Option<SurfacePoint>> {
+    if options.find_landmasses {
+        vec![vec![None; options.slicing.width]; options.slicing.get_slice_height(id)]
+    } else {
+        vec![]
+    }
+}
+
+// pixels outside of the projection are None
+fn gen_samples(id: u8, options: &RenderOptions) -> Vec2D<Option<PixelSample>> {
+    if options.defers_coloring() {
+        vec![vec![None; options.slicing.width]; options.slicing.get_slice_height(id)]
+    } else {
+        vec![]
+    }
+}
+
+// pixels outside of the projection are NaN
+fn gen_layers(id: u8, options: &RenderOptions) -> Vec<Vec2D<f32>> {
+    vec![
+        vec![vec![f32::NAN; options.slicing.width]; options.slicing.get_slice_height(id)];
+        options.layers.len()
+    ]
+}
+
 pub fn execute(args: Args) {
-    let options = args.into_options();
+    let mut options = args.into_options();
+    if let Some(land_fraction) = options.land_fraction {
+        options.initial_altitude = altitude::solve_initial_altitude(&options, land_fraction);
+        println!("Using initial altitude {} for a land fraction of {land_fraction}", options.initial_altitude);
+    }
     let state = Arc::new(RenderState::new(options.clone()));
 
     validate_size(state.clone());
@@ -302,43 +527,8 @@ pub fn execute(args: Args) {
             let state = state.clone();
             scope.spawn(move || {
                 let mut thread_state = ThreadState::new(thread_id, state.options.clone());
-                let (projection, has_per_row_subdivision) = match state.options.projection {
-                    ProjectionMode::Azimuthal => {
-                        (Azimuthal::create(thread_id, &thread_state.options), false)
-                    }
-                    ProjectionMode::Conical => {
-                        (Conical::create(thread_id, &thread_state.options), false)
-                    }
-                    ProjectionMode::Gnomonic => {
-                        (Gnomonic::create(thread_id, &thread_state.options), false)
-                    }
-                    ProjectionMode::Icosahedral => {
-                        (Icosahedral::create(thread_id, &thread_state.options), false)
-                    }
-                    ProjectionMode::Mercator => {
-                        (Mercator::create(thread_id, &thread_state.options), true)
-                    }
-                    ProjectionMode::Mollweide => {
-                        (Mollweide::create(thread_id, &thread_state.options), true)
-                    }
-                    ProjectionMode::Orthographic => (
-                        Orthographic::create(thread_id, &thread_state.options),
-                        false,
-                    ),
-                    ProjectionMode::Peters => {
-                        (Peters::create(thread_id, &thread_state.options), true)
-                    }
-                    ProjectionMode::Sinusoidal => {
-                        (Sinusoidal::create(thread_id, &thread_state.options), true)
-                    }
-                    ProjectionMode::Square => {
-                        (Square::create(thread_id, &thread_state.options), true)
-                    }
-                    ProjectionMode::Stereographic => (
-                        Stereographic::create(thread_id, &thread_state.options),
-                        false,
-                    ),
-                };
+                let (projection, has_per_row_subdivision) =
+                    create_projector(thread_id, &thread_state.options);
                 let mut time = Utc::now();
                 let slice_height = thread_state.options.slicing.get_slice_height(thread_id);
                 if !has_per_row_subdivision {
@@ -359,7 +549,7 @@ pub fn execute(args: Args) {
                     if h > 0 && h % 100 == 0 {
                         let ms = (Utc::now() - time).num_milliseconds();
                         let pixels_per_second =
-                            (100_000 * thread_state.options.slicing.width) as i64 / ms;
+                            (100_000 * thread_state.options.slicing.width) as i64 / ms.max(1);
                         println!("Thread {thread_id} completed line {h} - {pixels_per_second}pps",);
                         time = Utc::now()
                     }
@@ -370,6 +560,20 @@ pub fn execute(args: Args) {
         }
     });
 
+    if options.defers_coloring() {
+        if options.erosion_iterations > 0 {
+            erosion::erode(&state);
+        }
+        color_samples(state.clone());
+        if options.draw_rivers || options.draw_lakes {
+            hydrology::draw_water(&state);
+        }
+    }
+
+    if options.draw_face_edges && matches!(options.projection, ProjectionMode::Icosahedral) {
+        icosahedral::draw_face_edges(&state);
+    }
+
     gridlines::generate_gridlines(state.clone());
 
     smooth_shading(state.clone());
@@ -379,6 +583,33 @@ pub fn execute(args: Args) {
     println!("Render completed in {time} seconds");
 }
 
+/// Colors the pixels kept by a render that deferred coloring, one thread per slice
+fn color_samples(state: Arc<RenderState>) {
+    let slice_count = state.options.slicing.slice_count as usize;
+    let samples = std::mem::replace(&mut *state.samples.write().unwrap(), vec![vec![]; slice_count]);
+    thread::scope(|scope| {
+        for (thread_id, slice) in samples.into_iter().enumerate() {
+            let state = state.clone();
+            scope.spawn(move || {
+                let mut thread_state = ThreadState::new(thread_id as u8, state.options.clone());
+                for (h, row) in slice.iter().enumerate() {
+                    for (w, sample) in row.iter().enumerate() {
+                        if let Some(sample) = sample {
+                            thread_state.rain_shadow = sample.rain_shadow;
+                            thread_state.shade = sample.shade;
+                            thread_state.normal = sample.normal;
+                            color_pixel(&mut thread_state, &sample.point, sample.altitude, h, w);
+                        }
+                    }
+                }
+                // keep the samples for passes that draw over the colors
+                thread_state.samples = slice;
+                commit_render_data(thread_id as u8, thread_state, state.clone());
+            });
+        }
+    });
+}
+
 pub fn commit_render_data(
     thread_id: u8,
     thread_state: ThreadState,
@@ -392,6 +623,25 @@ pub fn commit_render_data(
         render_state.heightfield.write().unwrap()[thread_id as usize] = thread_state.heightfield;
     }
 
+    if thread_state.options.generate_normal_map {
+        render_state.normal_map.write().unwrap()[thread_id as usize] = thread_state.normal_map;
+    }
+
+    render_state.layers.write().unwrap()[thread_id as usize] = thread_state.layers;
+
+    if thread_state.options.find_landmasses {
+        render_state.surface.write().unwrap()[thread_id as usize] = thread_state.surface;
+    }
+
+    if !thread_state.samples.is_empty() {
+        render_state.samples.write().unwrap()[thread_id as usize] = thread_state.samples;
+    }
+
+    let mut biome_counts = render_state.biome_counts.write().unwrap();
+    for (total, count) in biome_counts.iter_mut().zip(thread_state.biome_counts) {
+        *total += count;
+    }
+
     if !thread_state
         .options
         .filetypes
@@ -1,8 +1,81 @@
-use crate::geometry::{side_check, Vertex};
+use crate::geometry::{side_check, Tetra, Vertex};
 use crate::math::{distance_squared};
-use crate::render::ThreadState;
+use crate::projection::{get_depth_for_pixel_angle, vertex_from_lat_long};
+use crate::projection::healpix::HealpixPixel;
+use crate::render::{RenderOptions, ThreadState};
+use crate::terrain::LatLong;
 use std::f64::consts::PI;
 use std::mem;
+use std::thread;
+
+// points sampled over the sphere for whole planet measurements, and the subdivision depth used for them
+pub const SPHERE_SAMPLES: usize = 20_000;
+const SPHERE_SAMPLE_DEPTH: u8 = 30;
+
+/// Creates a thread state for sampling altitudes outside of a render
+pub fn create_sampling_state(options: &RenderOptions) -> ThreadState {
+    let mut state = ThreadState::new(0, options.clone());
+    state.starting_subdivision_depth = SPHERE_SAMPLE_DEPTH;
+    state
+}
+
+/// Spreads points evenly over the sphere on a Fibonacci spiral, so each covers the same area
+pub fn get_sphere_samples(count: usize) -> impl Iterator<Item = Vertex> {
+    let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+    (0..count).map(move |i| {
+        let y = 1.0 - (2 * i + 1) as f64 / count as f64;
+        let r = (1.0 - y * y).sqrt();
+        let theta = golden_angle * i as f64;
+        Vertex::from_point(r * theta.cos(), y, r * theta.sin())
+    })
+}
+
+/// Altitude at the center of each pixel of a HEALPix grid, in the grid's order. Each of the
+/// twelve base faces is sampled on its own thread, at the detail a map of that resolution gets.
+pub fn get_healpix_altitudes(options: &RenderOptions, nside: usize) -> Vec<f32> {
+    let depth = get_depth_for_pixel_angle((PI / 3.0).sqrt() / nside as f64);
+    let axis = &LatLong::new_with_trig(0.0, 0.0);
+    let faces = thread::scope(|scope| {
+        let threads = (0..12)
+            .map(|face| {
+                scope.spawn(move || {
+                    let mut state = create_sampling_state(options);
+                    state.starting_subdivision_depth = depth;
+                    HealpixPixel::all(nside)
+                        .skip(face * nside * nside)
+                        .take(nside * nside)
+                        .map(|pixel| {
+                            let (latitude, longitude) = pixel.get_center(nside);
+                            let p = vertex_from_lat_long(axis, latitude, longitude);
+                            (pixel.get_index(nside, options.healpix_order), calc_altitude(&mut state, &p))
+                        })
+                        .collect::<Vec<(usize, f64)>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().map(|x| x.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    let mut altitudes = vec![0.0; 12 * nside * nside];
+    for (index, altitude) in faces.into_iter().flatten() {
+        altitudes[index] = altitude as f32;
+    }
+    altitudes
+}
+
+/// Finds the initial altitude that gives a fraction of land. Every altitude moves with the
+/// initial altitude, so this lowers it by the altitude that the fraction of evenly spread samples
+/// lie above.
+pub fn solve_initial_altitude(options: &RenderOptions, land_fraction: f64) -> f64 {
+    let mut state = create_sampling_state(options);
+    let mut altitudes = get_sphere_samples(SPHERE_SAMPLES)
+        .map(|p| calc_altitude(&mut state, &p))
+        .collect::<Vec<f64>>();
+    altitudes.sort_by(f64::total_cmp);
+
+    let index = ((1.0 - land_fraction) * SPHERE_SAMPLES as f64) as usize;
+    options.initial_altitude - altitudes[index.min(SPHERE_SAMPLES - 1)]
+}
 
 // planet1() & planet()
 pub fn calc_altitude(state: &mut ThreadState, p: &Vertex) -> f64 {
@@ -129,7 +202,7 @@ pub fn calc_altitude(state: &mut ThreadState, p: &Vertex) -> f64 {
         }
 
         /* calculate approximate rain shadow for new point */
-        if e.altitude <= 0.0 || !(state.options.calculate_rainfall || state.options.show_biomes) {
+        if e.altitude <= 0.0 || !state.options.calculate_rain_shadow {
             e.rain_shadow = 0.0;
         } else {
             x1 = 0.5 * (tetra.a.x + tetra.b.x);
@@ -179,24 +252,29 @@ pub fn calc_altitude(state: &mut ThreadState, p: &Vertex) -> f64 {
         continue;
     }
 
-    match state.options.shading_level {
+    shade_surface(&state.options, &tetra, p, &mut state.shade, &mut state.normal);
+    state.rain_shadow = 0.25
+        * (tetra.a.rain_shadow + tetra.b.rain_shadow + tetra.c.rain_shadow + tetra.d.rain_shadow);
+    0.25 * (tetra.a.altitude + tetra.b.altitude + tetra.c.altitude + tetra.d.altitude)
+}
+
+/// Sets the relief shading and the normal map's normal of a point from the tetrahedron around it
+pub fn shade_surface(
+    options: &RenderOptions,
+    tetra: &Tetra,
+    p: &Vertex,
+    shade: &mut u8,
+    normal: &mut [u8; 3],
+) {
+    let mut x1;
+    let mut y1;
+    let mut z1;
+    let mut l1;
+    let mut tmp;
+    match options.shading_level {
         1 | 2 => {
             /* bump map */
-            x1 = 0.25 * (tetra.a.x + tetra.b.x + tetra.c.x + tetra.d.x);
-            x1 = tetra.a.altitude * (x1 - tetra.a.x)
-                + tetra.b.altitude * (x1 - tetra.b.x)
-                + tetra.c.altitude * (x1 - tetra.c.x)
-                + tetra.d.altitude * (x1 - tetra.d.x);
-            y1 = 0.25 * (tetra.a.y + tetra.b.y + tetra.c.y + tetra.d.y);
-            y1 = tetra.a.altitude * (y1 - tetra.a.y)
-                + tetra.b.altitude * (y1 - tetra.b.y)
-                + tetra.c.altitude * (y1 - tetra.c.y)
-                + tetra.d.altitude * (y1 - tetra.d.y);
-            z1 = 0.25 * (tetra.a.z + tetra.b.z + tetra.c.z + tetra.d.z);
-            z1 = tetra.a.altitude * (z1 - tetra.a.z)
-                + tetra.b.altitude * (z1 - tetra.b.z)
-                + tetra.c.altitude * (z1 - tetra.c.z)
-                + tetra.d.altitude * (z1 - tetra.d.z);
+            (x1, y1, z1) = get_gradient(tetra);
             l1 = (x1 * x1 + y1 * y1 + z1 * z1).sqrt();
             if l1 == 0.0 {
                 l1 = 1.0;
@@ -208,16 +286,16 @@ pub fn calc_altitude(state: &mut ThreadState, p: &Vertex) -> f64 {
             let y2 = y1 * tmp - (p.x * p.y * x1 + p.y * p.z * z1) / tmp;
             let z2 = (p.x * z1 - x1 * p.z) / tmp;
 
-            state.shade = ((-(PI * state.options.light.longitude / 180.0).sin() * y2
-                - (PI * state.options.shading_level as f64 / 180.0).cos() * z2)
+            *shade = ((-(PI * options.light.longitude / 180.0).sin() * y2
+                - (PI * options.shading_level as f64 / 180.0).cos() * z2)
                 / l1
                 * 48.0
                 + 128.0)
                 .clamp(10., 255.) as u8;
-            if state.options.shading_level == 2
+            if options.shading_level == 2
                 && (tetra.a.altitude + tetra.b.altitude + tetra.c.altitude + tetra.d.altitude) < 0.0
             {
-                state.shade = 150;
+                *shade = 150;
             }
         }
         3 => {
@@ -230,21 +308,7 @@ pub fn calc_altitude(state: &mut ThreadState, p: &Vertex) -> f64 {
                 z1 = p.z; /* (x1,y1,z1) = normal vector */
             } else {
                 /* add bumpmap effect */
-                x1 = 0.25 * (tetra.a.x + tetra.b.x + tetra.c.x + tetra.d.x);
-                x1 = tetra.a.altitude * (x1 - tetra.a.x)
-                    + tetra.b.altitude * (x1 - tetra.b.x)
-                    + tetra.c.altitude * (x1 - tetra.c.x)
-                    + tetra.d.altitude * (x1 - tetra.d.x);
-                y1 = 0.25 * (tetra.a.y + tetra.b.y + tetra.c.y + tetra.d.y);
-                y1 = tetra.a.altitude * (y1 - tetra.a.y)
-                    + tetra.b.altitude * (y1 - tetra.b.y)
-                    + tetra.c.altitude * (y1 - tetra.c.y)
-                    + tetra.d.altitude * (y1 - tetra.d.y);
-                z1 = 0.25 * (tetra.a.z + tetra.b.z + tetra.c.z + tetra.d.z);
-                z1 = tetra.a.altitude * (z1 - tetra.a.z)
-                    + tetra.b.altitude * (z1 - tetra.b.z)
-                    + tetra.c.altitude * (z1 - tetra.c.z)
-                    + tetra.d.altitude * (z1 - tetra.d.z);
+                (x1, y1, z1) = get_gradient(tetra);
                 l1 = 5.0 * (x1 * x1 + y1 * y1 + z1 * z1).sqrt();
                 x1 += p.x * l1;
                 y1 += p.y * l1;
@@ -254,18 +318,118 @@ pub fn calc_altitude(state: &mut ThreadState, p: &Vertex) -> f64 {
             if l1 == 0.0 {
                 l1 = 1.0;
             }
-            let x2 = (PI * state.options.light.longitude / 180.0 - 0.5 * PI).cos()
-                * (PI * state.options.light.latitude / 180.0).cos();
-            let y2 = -(PI * state.options.light.latitude / 180.0).sin();
-            let z2 = -(PI * state.options.light.longitude / 180.0 - 0.5 * PI).sin()
-                * (PI * state.options.light.latitude / 180.0).cos();
+            let x2 = (PI * options.light.longitude / 180.0 - 0.5 * PI).cos()
+                * (PI * options.light.latitude / 180.0).cos();
+            let y2 = -(PI * options.light.latitude / 180.0).sin();
+            let z2 = -(PI * options.light.longitude / 180.0 - 0.5 * PI).sin()
+                * (PI * options.light.latitude / 180.0).cos();
 
-            state.shade =
+            *shade =
                 ((x1 * x2 + y1 * y2 + z1 * z2) / l1 * 170.0 + 10.0).clamp(10., 255.) as u8;
         }
         _ => {}
     }
-    state.rain_shadow = 0.25
-        * (tetra.a.rain_shadow + tetra.b.rain_shadow + tetra.c.rain_shadow + tetra.d.rain_shadow);
-    0.25 * (tetra.a.altitude + tetra.b.altitude + tetra.c.altitude + tetra.d.altitude)
+    if options.generate_normal_map {
+        *normal = get_surface_normal(tetra, p, options.normal_map_strength);
+    }
+}
+
+/// Sum of each vertex's altitude times its offset to the center of the tetrahedron.
+/// This points downhill, with a length proportional to the slope.
+#[inline(always)]
+fn get_gradient(tetra: &Tetra) -> (f64, f64, f64) {
+    let mut x1 = 0.25 * (tetra.a.x + tetra.b.x + tetra.c.x + tetra.d.x);
+    x1 = tetra.a.altitude * (x1 - tetra.a.x)
+        + tetra.b.altitude * (x1 - tetra.b.x)
+        + tetra.c.altitude * (x1 - tetra.c.x)
+        + tetra.d.altitude * (x1 - tetra.d.x);
+    let mut y1 = 0.25 * (tetra.a.y + tetra.b.y + tetra.c.y + tetra.d.y);
+    y1 = tetra.a.altitude * (y1 - tetra.a.y)
+        + tetra.b.altitude * (y1 - tetra.b.y)
+        + tetra.c.altitude * (y1 - tetra.c.y)
+        + tetra.d.altitude * (y1 - tetra.d.y);
+    let mut z1 = 0.25 * (tetra.a.z + tetra.b.z + tetra.c.z + tetra.d.z);
+    z1 = tetra.a.altitude * (z1 - tetra.a.z)
+        + tetra.b.altitude * (z1 - tetra.b.z)
+        + tetra.c.altitude * (z1 - tetra.c.z)
+        + tetra.d.altitude * (z1 - tetra.d.z);
+    (x1, y1, z1)
+}
+
+/// Tangent-space surface normal encoded as RGB, with red pointing east and green pointing north.
+/// The sea surface is flat.
+fn get_surface_normal(tetra: &Tetra, p: &Vertex, strength: f64) -> [u8; 3] {
+    if tetra.a.altitude + tetra.b.altitude + tetra.c.altitude + tetra.d.altitude <= 0.0 {
+        return [128, 128, 255];
+    }
+
+    // for small tetrahedra the weighted offsets approximate the altitude gradient
+    // scaled by a third of the summed squared offsets, in the opposite direction
+    let (x1, y1, z1) = get_gradient(tetra);
+    let cx = 0.25 * (tetra.a.x + tetra.b.x + tetra.c.x + tetra.d.x);
+    let cy = 0.25 * (tetra.a.y + tetra.b.y + tetra.c.y + tetra.d.y);
+    let cz = 0.25 * (tetra.a.z + tetra.b.z + tetra.c.z + tetra.d.z);
+    let spread = [&tetra.a, &tetra.b, &tetra.c, &tetra.d]
+        .iter()
+        .map(|v| (v.x - cx).powi(2) + (v.y - cy).powi(2) + (v.z - cz).powi(2))
+        .sum::<f64>();
+    let k = if spread > 0.0 { -3.0 / spread } else { 0.0 };
+
+    let mut tmp = (1.0 - p.y * p.y).sqrt();
+    if tmp < 0.0001 {
+        tmp = 0.0001;
+    }
+    // the y axis points south, so north is towards decreasing y
+    let east = k * (p.z * x1 - p.x * z1) / tmp;
+    let north = k * (p.x * p.y * x1 - (1.0 - p.y * p.y) * y1 + p.y * p.z * z1) / tmp;
+
+    let (nx, ny) = (-strength * east, -strength * north);
+    let l = (nx * nx + ny * ny + 1.0).sqrt();
+    [
+        ((nx / l * 0.5 + 0.5) * 255.0).round() as u8,
+        ((ny / l * 0.5 + 0.5) * 255.0).round() as u8,
+        ((1.0 / l * 0.5 + 0.5) * 255.0).round() as u8,
+    ]
+}
+
+#[test]
+fn test_sphere_samples() {
+    let samples = get_sphere_samples(1000).collect::<Vec<Vertex>>();
+    assert_eq!(samples.len(), 1000);
+    for p in &samples {
+        assert!((p.x * p.x + p.y * p.y + p.z * p.z - 1.0).abs() < 1e-12);
+    }
+
+    // evenly spread points balance out
+    let (x, y, z) = samples
+        .iter()
+        .fold((0.0, 0.0, 0.0), |a, p| (a.0 + p.x, a.1 + p.y, a.2 + p.z));
+    assert!(x.abs() < 1.0 && y.abs() < 1e-9 && z.abs() < 1.0);
+}
+
+#[test]
+fn test_solve_initial_altitude() {
+    let mut options = crate::projection::get_test_options(["-s", "0.42"]);
+    // measured on other points than the solver's, so only to within sampling
+    let mut get_land_fraction = |land_fraction: f64| {
+        options.initial_altitude = solve_initial_altitude(&options, land_fraction);
+        let mut state = create_sampling_state(&options);
+        let land = get_sphere_samples(5000).filter(|p| calc_altitude(&mut state, p) > 0.0).count();
+        land as f64 / 5000.0
+    };
+    let fraction = get_land_fraction(0.3);
+    assert!((fraction - 0.3).abs() < 0.02, "{fraction} of the planet is land");
+
+    // the highest and lowest samples end up at sea level
+    assert!(get_land_fraction(0.0) < 0.01);
+    assert!(get_land_fraction(1.0) > 0.99);
+}
+
+#[test]
+fn test_healpix_altitudes() {
+    // the coarsest grid has a pixel to each base face
+    let options = crate::projection::get_test_options([]);
+    let altitudes = get_healpix_altitudes(&options, 1);
+    assert_eq!(altitudes.len(), 12);
+    assert!(altitudes.iter().all(|x| x.is_finite()));
 }
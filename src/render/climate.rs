@@ -0,0 +1,154 @@
+use crate::geometry::Vertex;
+use crate::terrain::koppen::Climate;
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+// land warms and cools by about half the change in sunlight between seasons
+const SEASONAL_RESPONSE: f64 = 0.5;
+
+// orbital phases sampled through a year
+const PHASE_SAMPLES: usize = 24;
+
+/// Time of year that temperature and rainfall are calculated for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Season {
+    Annual,
+    /// Orbital phase in radians, 0 at the northern spring equinox
+    Phase(f64),
+}
+
+impl Season {
+    /// Parses annual, summer or winter (of the northern hemisphere), or an orbital phase in degrees
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "annual" => Ok(Season::Annual),
+            "summer" => Ok(Season::Phase(FRAC_PI_2)),
+            "winter" => Ok(Season::Phase(3.0 * FRAC_PI_2)),
+            x => x
+                .parse::<f64>()
+                .map(|x| Season::Phase(x.to_radians()))
+                .map_err(|_| "expected annual, summer, winter or an orbital phase in degrees".into()),
+        }
+    }
+}
+
+// scale: -0.1 to 0.1 corresponds to -30 to +30 degrees Celsius
+pub fn get_temperature(sun: f64, alt: f64) -> f64 {
+    if alt < 0. {
+        sun / 8. + alt * 0.3 // deep water colder
+    } else {
+        sun / 8. - alt * 1.2 // high altitudes colder
+    }
+}
+
+// calculate rainfall based on temperature and latitude
+// rainfall approximately proportional to temperature but reduced
+//      near horse latitudes (+/- 30 degrees, y=0.5) and reduced for rain shadow
+pub fn get_rainfall(temp: f64, y: f64, rain_shadow: f64) -> f64 {
+    let y2: f64 = y.abs() - 0.5;
+    let mut rain = temp * 0.65 + 0.1 - 0.011 / (y2 * y2 + 0.1);
+    rain += 0.03 * rain_shadow;
+    if rain < 0.0 {
+        rain = 0.0;
+    }
+    rain
+}
+
+/// Returns the sunlight used for temperature, and the y coordinate the rainfall belts are placed by.
+/// Without a season the planet is treated as having no axial tilt.
+pub fn get_sunlight(y: f64, obliquity: f64, season: Option<Season>) -> (f64, f64) {
+    let latitude = (-y).asin();
+    match season {
+        None => ((1. - y * y).sqrt(), y),
+        Some(Season::Annual) => (get_annual_insolation(latitude, obliquity), y),
+        Some(Season::Phase(phase)) => {
+            let annual = get_annual_insolation(latitude, obliquity);
+            get_seasonal_sunlight(latitude, annual, obliquity, phase)
+        }
+    }
+}
+
+/// Estimates the warmest and coldest months by sampling the seasons through a year, with the
+/// rainfall of each taken as that of the summer and winter halves of the year
+pub fn get_climate(p: &Vertex, alt: f64, rain_shadow: f64, obliquity: f64) -> Climate {
+    let latitude = (-p.y).asin();
+    let annual = get_annual_insolation(latitude, obliquity);
+
+    let months = (0..PHASE_SAMPLES)
+        .map(|i| {
+            let phase = TAU * (i as f64 + 0.5) / PHASE_SAMPLES as f64;
+            let (sun, rain_y) = get_seasonal_sunlight(latitude, annual, obliquity, phase);
+            let temp = get_temperature(sun, alt);
+            (temp, get_rainfall(temp, rain_y, rain_shadow))
+        })
+        .collect::<Vec<(f64, f64)>>();
+    let summer = months.iter().copied().fold((f64::MIN, 0.0), |a, x| if x.0 > a.0 { x } else { a });
+    let winter = months.iter().copied().fold((f64::MAX, 0.0), |a, x| if x.0 < a.0 { x } else { a });
+
+    Climate {
+        warmest_month: get_celsius(summer.0),
+        coldest_month: get_celsius(winter.0),
+        summer_rain: get_millimetres(summer.1) / 12.0,
+        winter_rain: get_millimetres(winter.1) / 12.0,
+    }
+}
+
+pub fn get_celsius(temp: f64) -> f64 {
+    temp * 300.0
+}
+
+/// Rainfall in mm a year, on the scale of the biome grid where each row is 100 mm
+pub fn get_millimetres(rain: f64) -> f64 {
+    ((rain * 300.0 - 9.0) * 100.0).max(0.0)
+}
+
+/// Damps the sunlight of a season towards the annual mean, and moves the rainfall belts after
+/// the sun by half its declination
+fn get_seasonal_sunlight(latitude: f64, annual: f64, obliquity: f64, phase: f64) -> (f64, f64) {
+    let declination = get_declination(obliquity, phase);
+    let sun = annual + SEASONAL_RESPONSE * (get_insolation(latitude, declination) - annual);
+    (sun, -(latitude - declination / 2.0).sin())
+}
+
+fn get_declination(obliquity: f64, phase: f64) -> f64 {
+    (obliquity.sin() * phase.sin()).asin()
+}
+
+/// Daily mean sunlight at a latitude, relative to the equator at an equinox
+fn get_insolation(latitude: f64, declination: f64) -> f64 {
+    // hour angle of sunset, clamped for polar day and night
+    let sunset = (-latitude.tan() * declination.tan()).clamp(-1.0, 1.0).acos();
+    sunset * latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * sunset.sin()
+}
+
+fn get_annual_insolation(latitude: f64, obliquity: f64) -> f64 {
+    (0..PHASE_SAMPLES)
+        .map(|i| {
+            let phase = TAU * (i as f64 + 0.5) / PHASE_SAMPLES as f64;
+            get_insolation(latitude, get_declination(obliquity, phase))
+        })
+        .sum::<f64>()
+        / PHASE_SAMPLES as f64
+}
+
+#[test]
+fn test_insolation() {
+    let obliquity = 23.44_f64.to_radians();
+
+    // without tilt sunlight is the cosine of latitude all year
+    for latitude in [0.0, 0.5, 1.0, 1.5] {
+        assert!((get_annual_insolation(latitude, 0.0) - latitude.cos()).abs() < 1e-12);
+    }
+
+    // the poles have polar night in winter and more sunlight than the equator in summer
+    assert_eq!(get_insolation(FRAC_PI_2, -obliquity), 0.0);
+    assert!(get_insolation(FRAC_PI_2, obliquity) > get_insolation(0.0, obliquity));
+
+    // northern summer is southern winter
+    let (north, _) = get_sunlight(-0.7, obliquity, Some(Season::Phase(FRAC_PI_2)));
+    let (south, _) = get_sunlight(0.7, obliquity, Some(Season::Phase(FRAC_PI_2)));
+    assert!(north > south);
+
+    assert_eq!(Season::from_name("winter"), Ok(Season::Phase(3.0 * FRAC_PI_2)));
+    assert_eq!(Season::from_name("90"), Ok(Season::Phase(FRAC_PI_2)));
+    assert!(Season::from_name("spring").is_err());
+}
@@ -1,41 +1,78 @@
 use crate::geometry::Vertex;
 use crate::render::altitude::calc_altitude;
 use crate::render::ThreadState;
-use crate::terrain::biomes;
+use crate::render::landmass::SurfacePoint;
+use crate::render::layers::Layer;
+use crate::render::climate::{get_climate, get_rainfall, get_sunlight, get_temperature};
+use crate::terrain::koppen;
+
+/// Altitude and shading of a pixel kept for coloring after post-processing
+#[derive(Clone, Copy)]
+pub struct PixelSample {
+    pub point: Vertex,
+    pub altitude: f64,
+    pub rain_shadow: f64,
+    pub shade: u8,
+    pub normal: [u8; 3],
+}
 
 // planet0()
 pub fn render_pixel(thread_state: &mut ThreadState, p: &Vertex, h: usize, w: usize) {
+    let alt: f64 = calc_altitude(thread_state, p);
+    if thread_state.options.defers_coloring() {
+        thread_state.samples[h][w] = Some(PixelSample {
+            point: *p,
+            altitude: alt,
+            rain_shadow: thread_state.rain_shadow,
+            shade: thread_state.shade,
+            normal: thread_state.normal,
+        });
+        return;
+    }
+    color_pixel(thread_state, p, alt, h, w);
+}
+
+/// Colors a pixel from its altitude, with the rain shadow and shading already in the thread state
+pub fn color_pixel(thread_state: &mut ThreadState, p: &Vertex, raw_alt: f64, h: usize, w: usize) {
     let options = thread_state.options.clone();
-    let mut alt: f64 = calc_altitude(thread_state, p);
+    let mut alt = raw_alt;
 
     // calculate temperature based on altitude and latitude
     // scale: -0.1 to 0.1 corresponds to -30 to +30 degrees Celsius
-    let sun: f64 = (1. - p.y * p.y).sqrt(); //approximate amount of sunlight at latitude ranged from 0.1 to 1.1
+    let (sun, rain_y) = get_sunlight(p.y, options.obliquity, options.season);
 
-    let temp = if alt < 0. {
-        sun / 8. + alt * 0.3 // deep water colder
-    } else {
-        sun / 8. - alt * 1.2 // high altitudes colder
-    };
+    let temp = get_temperature(sun, alt);
 
     if options.use_temperature {
         alt = temp - 0.05;
     }
 
-    // calculate rainfall based on temperature and latitude
-    // rainfall approximately proportional to temperature but reduced
-    //      near horse latitudes (+/- 30 degrees, y=0.5) and reduced for rain shadow
-    let mut y2: f64 = p.y.abs() - 0.5;
-    let mut rain = temp * 0.65 + 0.1 - 0.011 / (y2 * y2 + 0.1);
-    rain += 0.03 * thread_state.rain_shadow;
-    if rain < 0.0 {
-        rain = 0.0;
-    }
+    let rain = get_rainfall(temp, rain_y, thread_state.rain_shadow);
 
     if options.calculate_rainfall {
         alt = rain - 0.02;
     }
 
+    for (i, layer) in options.layers.iter().enumerate() {
+        thread_state.layers[i][h][w] = match layer {
+            Layer::Altitude => raw_alt as f32,
+            Layer::Temperature => temp as f32,
+            Layer::Rainfall => rain as f32,
+            Layer::RainShadow => thread_state.rain_shadow as f32,
+            Layer::Biome if raw_alt <= 0.0 => 0.0,
+            Layer::Biome => options.biome_table.get_biome(temp, rain) as f32,
+        };
+    }
+
+    if options.find_landmasses {
+        thread_state.surface[h][w] = Some(SurfacePoint {
+            x: p.x as f32,
+            y: p.y as f32,
+            z: p.z as f32,
+            is_land: raw_alt > 0.0,
+        });
+    }
+
     // non-linear scaling to make flatter near sea level
     if options.use_nonlinear_altitude_scaling {
         alt = alt * alt * alt * 300.0;
@@ -46,19 +83,30 @@ pub fn render_pixel(thread_state: &mut ThreadState, p: &Vertex, h: usize, w: usi
         thread_state.heightfield[h][w] = (10_000_000.0 * alt) as i32;
     }
 
-    y2 = p.y.powi(8);
+    let y2 = p.y.powi(8);
 
     let color_table = &thread_state.options.color_table;
 
-    let color = if options.show_biomes {
-        let tt = ((rain * 300.0 - 9.0) as i32).clamp(0, 44) as u8;
-        let rr = ((temp * 300.0 + 10.0) as i32).clamp(0, 44) as u8;
-        let bio = biomes[tt as usize][rr as usize] as u16;
+    let color = if options.show_koppen {
+        if alt <= 0.0 {
+            thread_state.biome_counts[0] += 1;
+            let depth_level = (-10. * alt).min(1.);
+            let c = (color_table.sea_depth as f64 * depth_level) as u16;
+            color_table.sea_level - c
+        } else {
+            let zone = koppen::classify(&get_climate(p, raw_alt, thread_state.rain_shadow, options.obliquity));
+            thread_state.biome_counts[zone + 1] += 1;
+            color_table.lowest_land + zone as u16
+        }
+    } else if options.show_biomes {
+        let bio = options.biome_table.get_biome(temp, rain) as u16;
         if alt <= 0.0 {
+            thread_state.biome_counts[0] += 1;
             let depth_level = (-10. * alt).min(1.);
             let c = (color_table.sea_depth as f64 * depth_level) as u16;
             color_table.sea_level - c
         } else {
+            thread_state.biome_counts[bio as usize] += 1;
             bio - 64 + color_table.lowest_land // from LAND+2 to LAND+23
         }
     } else if alt <= 0. {
@@ -89,6 +137,9 @@ pub fn render_pixel(thread_state: &mut ThreadState, p: &Vertex, h: usize, w: usi
     if thread_state.shade > 0 {
         thread_state.shading[h][w] = thread_state.shade;
     }
+    if options.generate_normal_map {
+        thread_state.normal_map[h][w] = thread_state.normal;
+    }
 
     // store (x,y,z) coordinates for grid drawing
     // if vgrid != 0.0 {
@@ -100,3 +151,4 @@ pub fn render_pixel(thread_state: &mut ThreadState, p: &Vertex, h: usize, w: usi
     // }
     //
 }
+
@@ -0,0 +1,312 @@
+use crate::geometry::{Tetra, Vertex};
+use crate::render::altitude::shade_surface;
+use crate::render::color::PixelSample;
+use crate::render::RenderState;
+use crate::util::Vec2D;
+
+// altitudes are scaled so the mean slope between neighboring land pixels is this, which keeps
+// the droplet parameters below independent of the map's size and magnification
+const MEAN_SLOPE: f64 = 0.01;
+
+// droplet parameters, after Beyer (2015)
+const INERTIA: f64 = 0.05;
+const CAPACITY: f64 = 4.0;
+const MIN_CAPACITY_SLOPE: f64 = 0.01;
+const DEPOSITION: f64 = 0.3;
+const EROSION: f64 = 0.3;
+const EVAPORATION: f64 = 0.02;
+const GRAVITY: f64 = 4.0;
+const MAX_STEPS: usize = 64;
+const BRUSH_RADIUS: isize = 2;
+
+// land pixels for each droplet of an iteration
+const PIXELS_PER_DROPLET: usize = 8;
+
+// slope above which material slumps to a lower neighbor, and the fraction of the excess moved
+const TALUS: f64 = 0.05;
+const THERMAL_RATE: f64 = 0.1;
+
+/// Erodes the altitudes kept by a render that deferred coloring. Droplets are placed from the
+/// planet's seed, so the same options always give the same map.
+pub fn erode(state: &RenderState) {
+    let options = &state.options;
+    let mut samples = state.samples.write().unwrap();
+    let mut rows = samples.iter_mut().flatten().collect::<Vec<&mut Vec<Option<PixelSample>>>>();
+    let mut heights = rows
+        .iter()
+        .map(|row| row.iter().map(|x| x.map_or(f64::NAN, |x| x.altitude)).collect())
+        .collect::<Vec2D<f64>>();
+
+    erode_heights(
+        &mut heights,
+        options.erosion_iterations,
+        options.erosion_strength,
+        options.seeds.ss1.to_bits(),
+    );
+
+    for (row, heights) in rows.iter_mut().zip(&heights) {
+        for (sample, height) in row.iter_mut().zip(heights) {
+            if let Some(sample) = sample {
+                sample.altitude = *height;
+            }
+        }
+    }
+
+    // the relief shading and normals were found before the valleys were cut
+    if options.shading_level > 0 || options.generate_normal_map {
+        let points = rows
+            .iter()
+            .map(|row| row.iter().map(|x| x.map(|x| x.point)).collect())
+            .collect::<Vec2D<Option<Vertex>>>();
+        for (h, row) in rows.iter_mut().enumerate() {
+            for (w, sample) in row.iter_mut().enumerate() {
+                if let Some(sample) = sample {
+                    if let Some(tetra) = get_pixel_tetra(&points, &heights, h, w) {
+                        shade_surface(options, &tetra, &sample.point, &mut sample.shade, &mut sample.normal);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Tetrahedron of the eroded pixels to each side of a pixel, for shading it. A pixel at the edge
+/// of the map stands in for its missing neighbor.
+fn get_pixel_tetra(
+    points: &Vec2D<Option<Vertex>>,
+    heights: &Vec2D<f64>,
+    h: usize,
+    w: usize,
+) -> Option<Tetra> {
+    let get = |h: Option<usize>, w: Option<usize>| {
+        let (h, w) = (h?, w?);
+        let mut point = (*points.get(h)?.get(w)?)?;
+        point.altitude = heights[h][w];
+        (!point.altitude.is_nan()).then_some(point)
+    };
+    let center = get(Some(h), Some(w))?;
+    let (west, east) = (get(Some(h), w.checked_sub(1)), get(Some(h), w.checked_add(1)));
+    let (north, south) = (get(h.checked_sub(1), Some(w)), get(h.checked_add(1), Some(w)));
+    if (west.is_none() && east.is_none()) || (north.is_none() && south.is_none()) {
+        return None;
+    }
+    Some(Tetra::with_points(
+        west.unwrap_or(center),
+        east.unwrap_or(center),
+        north.unwrap_or(center),
+        south.unwrap_or(center),
+    ))
+}
+
+/// Runs droplet hydraulic erosion then thermal erosion for each iteration. Pixels outside the
+/// map are NaN and are left alone.
+fn erode_heights(heights: &mut Vec2D<f64>, iterations: u32, strength: f64, seed: u64) {
+    let scale = MEAN_SLOPE / get_mean_land_slope(heights);
+    if !scale.is_finite() {
+        return;
+    }
+    heights.iter_mut().flatten().for_each(|x| *x *= scale);
+
+    let land = heights
+        .iter()
+        .enumerate()
+        .flat_map(|(h, row)| row.iter().enumerate().filter(|x| *x.1 > 0.0).map(move |(w, _)| (h, w)))
+        .collect::<Vec<(usize, usize)>>();
+    let mut random = Random(seed | 1);
+    for _ in 0..iterations {
+        for _ in 0..land.len() / PIXELS_PER_DROPLET {
+            let (h, w) = land[(random.next() * land.len() as f64) as usize];
+            run_droplet(heights, h as f64 + random.next(), w as f64 + random.next(), strength);
+        }
+        slump(heights, strength);
+    }
+
+    heights.iter_mut().flatten().for_each(|x| *x /= scale);
+}
+
+/// Moves a droplet downhill, picking up material where it speeds up and dropping it where it
+/// slows down or fills a pit. It stops on reaching the sea or the edge of the map.
+fn run_droplet(heights: &mut Vec2D<f64>, mut y: f64, mut x: f64, strength: f64) {
+    let (mut dy, mut dx) = (0.0, 0.0);
+    let (mut speed, mut water, mut sediment) = (1.0, 1.0, 0.0);
+
+    for _ in 0..MAX_STEPS {
+        let Some((height, gy, gx)) = get_height_and_gradient(heights, y, x) else {
+            break;
+        };
+        dy = dy * INERTIA - gy * (1.0 - INERTIA);
+        dx = dx * INERTIA - gx * (1.0 - INERTIA);
+        let length = (dy * dy + dx * dx).sqrt();
+        if length < f64::EPSILON {
+            break;
+        }
+        let (old_y, old_x) = (y, x);
+        y += dy / length;
+        x += dx / length;
+        let Some((new_height, _, _)) = get_height_and_gradient(heights, y, x) else {
+            break;
+        };
+
+        let dh = new_height - height;
+        let capacity = (-dh).max(MIN_CAPACITY_SLOPE) * speed * water * CAPACITY;
+        if sediment > capacity || dh > 0.0 {
+            let amount = if dh > 0.0 {
+                dh.min(sediment)
+            } else {
+                (sediment - capacity) * DEPOSITION
+            };
+            sediment -= amount;
+            deposit(heights, old_y, old_x, amount);
+        } else {
+            let amount = ((capacity - sediment) * (EROSION * strength).min(1.0)).min(-dh);
+            sediment += amount;
+            erode_around(heights, old_y, old_x, amount);
+        }
+
+        speed = (speed * speed - dh * GRAVITY).max(0.0).sqrt();
+        water *= 1.0 - EVAPORATION;
+        if new_height <= 0.0 {
+            break;
+        }
+    }
+}
+
+/// Bilinear height and gradient at a point between pixel centers, if all four pixels are on the map
+fn get_height_and_gradient(heights: &Vec2D<f64>, y: f64, x: f64) -> Option<(f64, f64, f64)> {
+    if y < 0.0 || x < 0.0 {
+        return None;
+    }
+    let (h, w) = (y as usize, x as usize);
+    let (v, u) = (y - h as f64, x - w as f64);
+    let nw = *heights.get(h)?.get(w)?;
+    let ne = *heights.get(h)?.get(w + 1)?;
+    let sw = *heights.get(h + 1)?.get(w)?;
+    let se = *heights.get(h + 1)?.get(w + 1)?;
+    if [nw, ne, sw, se].iter().any(|x| x.is_nan()) {
+        return None;
+    }
+
+    let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+    let gy = (sw - nw) * (1.0 - u) + (se - ne) * u;
+    let gx = (ne - nw) * (1.0 - v) + (se - sw) * v;
+    Some((height, gy, gx))
+}
+
+/// Spreads material over the four pixels around a point
+fn deposit(heights: &mut Vec2D<f64>, y: f64, x: f64, amount: f64) {
+    let (h, w) = (y as usize, x as usize);
+    let (v, u) = (y - h as f64, x - w as f64);
+    heights[h][w] += amount * (1.0 - u) * (1.0 - v);
+    heights[h][w + 1] += amount * u * (1.0 - v);
+    heights[h + 1][w] += amount * (1.0 - u) * v;
+    heights[h + 1][w + 1] += amount * u * v;
+}
+
+/// Removes material from the pixels within the brush radius, weighted towards the nearest
+fn erode_around(heights: &mut Vec2D<f64>, y: f64, x: f64, amount: f64) {
+    let (h, w) = (y.round() as isize, x.round() as isize);
+    let mut cells = vec![];
+    for bh in h - BRUSH_RADIUS..=h + BRUSH_RADIUS {
+        for bw in w - BRUSH_RADIUS..=w + BRUSH_RADIUS {
+            let distance = ((bh - h).pow(2) as f64 + (bw - w).pow(2) as f64).sqrt();
+            let weight = BRUSH_RADIUS as f64 - distance;
+            if weight <= 0.0 || bh < 0 || bw < 0 {
+                continue;
+            }
+            let (bh, bw) = (bh as usize, bw as usize);
+            if heights.get(bh).and_then(|x| x.get(bw)).is_some_and(|x| !x.is_nan()) {
+                cells.push((bh, bw, weight));
+            }
+        }
+    }
+    let total = cells.iter().map(|x| x.2).sum::<f64>();
+    for (bh, bw, weight) in cells {
+        heights[bh][bw] -= amount * weight / total;
+    }
+}
+
+/// Thermal erosion: material slumps from each pixel to lower neighbors steeper than the talus slope
+fn slump(heights: &mut Vec2D<f64>, strength: f64) {
+    // a pixel can lose to all four neighbors, so more than a quarter of the excess overshoots
+    let rate = (THERMAL_RATE * strength).min(0.25);
+    let mut changes = vec![vec![0.0; heights.first().map_or(0, |x| x.len())]; heights.len()];
+    for h in 0..heights.len() {
+        for w in 0..heights[h].len() {
+            for (nh, nw) in [(h + 1, w), (h, w + 1)] {
+                let Some(neighbor) = heights.get(nh).and_then(|x| x.get(nw)) else {
+                    continue;
+                };
+                let difference = heights[h][w] - neighbor;
+                if difference.abs() > TALUS {
+                    let amount = rate * (difference.abs() - TALUS) / 2.0 * difference.signum();
+                    changes[h][w] -= amount;
+                    changes[nh][nw] += amount;
+                }
+            }
+        }
+    }
+    for (height, change) in heights.iter_mut().flatten().zip(changes.iter().flatten()) {
+        *height += change;
+    }
+}
+
+fn get_mean_land_slope(heights: &Vec2D<f64>) -> f64 {
+    let (mut total, mut count) = (0.0, 0);
+    for h in 0..heights.len() {
+        for w in 0..heights[h].len() {
+            for (nh, nw) in [(h + 1, w), (h, w + 1)] {
+                let Some(neighbor) = heights.get(nh).and_then(|x| x.get(nw)) else {
+                    continue;
+                };
+                if heights[h][w] > 0.0 && *neighbor > 0.0 {
+                    total += (heights[h][w] - neighbor).abs();
+                    count += 1;
+                }
+            }
+        }
+    }
+    total / count as f64
+}
+
+/// Xorshift generator, giving numbers from 0 up to 1
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_erosion() {
+    // a cone rising out of the sea, with a corner outside the map
+    let mut heights = (0..40)
+        .map(|h| {
+            (0..40)
+                .map(|w| {
+                    let distance = ((h as f64 - 20.0).powi(2) + (w as f64 - 20.0).powi(2)).sqrt();
+                    if h + w < 4 {
+                        f64::NAN
+                    } else {
+                        0.1 - 0.01 * distance
+                    }
+                })
+                .collect::<Vec<f64>>()
+        })
+        .collect::<Vec2D<f64>>();
+    let original = heights.clone();
+    erode_heights(&mut heights, 5, 1.0, 1);
+
+    let values = |x: &Vec2D<f64>| x.iter().flatten().map(|x| x.to_bits()).collect::<Vec<u64>>();
+    let total = |x: &Vec2D<f64>| x.iter().flatten().filter(|x| !x.is_nan()).sum::<f64>();
+    assert_eq!(heights.iter().flatten().filter(|x| x.is_nan()).count(), 10);
+    // droplets carry material out to sea
+    assert!(total(&heights) < total(&original));
+
+    let mut repeat = original.clone();
+    erode_heights(&mut repeat, 5, 1.0, 1);
+    assert_eq!(values(&heights), values(&repeat));
+}
@@ -0,0 +1,462 @@
+use crate::color::LAKE_SHADES;
+use crate::render::climate::{
+    get_celsius, get_millimetres, get_rainfall, get_sunlight, get_temperature,
+};
+use crate::render::color::PixelSample;
+use crate::render::{RenderOptions, RenderState};
+use crate::util::Vec2D;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f64::consts::SQRT_2;
+
+// filled pixels are raised this much above the pixel they drain to, so flats still drain
+const FILL_STEP: f64 = 1e-9;
+
+// evaporation from open water in metres a year at 0°C, and its increase with each degree
+const EVAPORATION: f64 = 0.3;
+const EVAPORATION_PER_DEGREE: f64 = 0.05;
+
+// basins shallower than this, in altitude, are noise in the terrain and are left dry
+const MIN_BASIN_DEPTH: f64 = 0.002;
+
+// radius of the planet in metres, and the length of a year in seconds, to turn rain into discharge
+const PLANET_RADIUS: f64 = 6_371_000.0;
+const SECONDS_PER_YEAR: f64 = 31_557_600.0;
+
+const NEIGHBORS: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+/// A stretch of river from its source or a confluence, to the sea, the edge of the map or the
+/// next confluence
+pub struct River {
+    /// Latitude and longitude of each pixel in degrees, in the direction of flow
+    pub points: Vec<(f64, f64)>,
+    /// Rain falling upstream of the last point on land, in cubic metres a second
+    pub discharge: f64,
+}
+
+/// Water standing in a closed basin, which fills up to its spill level if the rain draining
+/// into it outpaces evaporation from its surface, and otherwise keeps a smaller endorheic lake
+pub struct Basin {
+    /// Pixels below the spill level
+    pub cells: Vec<(usize, usize)>,
+    /// Altitude of the water surface, with pixels below it under water
+    pub level: f64,
+    pub overflows: bool,
+}
+
+/// Finds the lakes and rivers of a render that deferred coloring and draws them over the land
+pub fn draw_water(state: &RenderState) {
+    let options = &state.options;
+    let samples = state.samples.read().unwrap();
+    let rows = samples.iter().flatten().collect::<Vec<&Vec<Option<PixelSample>>>>();
+
+    let heights = get_heights(&rows);
+    let (rainfall, evaporation) = get_water_balance(&rows, options);
+    let filled = fill_depressions(&heights, FILL_STEP);
+    let mut downstream = get_downstream(&filled, &heights);
+    let mut flow = accumulate_flow(&filled, &downstream, &rainfall);
+
+    let mut lake_depths = vec![vec![None; heights.first().map_or(0, |x| x.len())]; heights.len()];
+    if options.draw_lakes {
+        let basins = find_basins(&heights, &fill_depressions(&heights, 0.0), &flow, &evaporation);
+        for basin in &basins {
+            for (h, w) in basin.cells.iter().copied().filter(|(h, w)| heights[*h][*w] < basin.level) {
+                lake_depths[h][w] = Some(basin.level - heights[h][w]);
+            }
+            // water reaching a basin that never overflows goes no further
+            if !basin.overflows {
+                basin.cells.iter().for_each(|(h, w)| downstream[*h][*w] = None);
+            }
+        }
+        flow = accumulate_flow(&filled, &downstream, &rainfall);
+
+        let lakes = basins.iter().filter(|x| x.cells.iter().any(|(h, w)| heights[*h][*w] < x.level));
+        let endorheic = lakes.clone().filter(|x| !x.overflows).count();
+        println!("Found {} lakes, {endorheic} of them in endorheic basins", lakes.count());
+    }
+    let is_river = |h: usize, w: usize| {
+        options.draw_rivers
+            && heights[h][w] > 0.0
+            && lake_depths[h][w].is_none()
+            && flow[h][w] >= options.river_threshold
+    };
+
+    let color_table = &options.color_table;
+    let mut canvas = state.canvas.write().unwrap();
+    if !canvas.iter().all(|x| x.is_empty()) {
+        for (ahi, row) in heights.iter().enumerate() {
+            let (vi, hi) = options.slicing.translate_index(ahi);
+            for w in 0..row.len() {
+                if let Some(depth) = lake_depths[ahi][w] {
+                    let depth_level = (10.0 * depth).min(1.0);
+                    canvas[vi][hi][w] = color_table.lake + (depth_level * (LAKE_SHADES - 1) as f64).round() as u16;
+                } else if is_river(ahi, w) {
+                    canvas[vi][hi][w] = color_table.river;
+                }
+            }
+        }
+    }
+
+    if options.draw_rivers {
+        let rivers = trace_rivers(&downstream, &flow, &is_river, |h, w| {
+            let point = rows[h][w].unwrap().point;
+            ((-point.y).clamp(-1.0, 1.0).asin().to_degrees(), point.x.atan2(point.z).to_degrees())
+        });
+        println!("Found {} river stretches", rivers.len());
+        *state.rivers.write().unwrap() = rivers;
+    }
+}
+
+/// Altitude of each pixel, NaN outside the map
+fn get_heights(rows: &[&Vec<Option<PixelSample>>]) -> Vec2D<f64> {
+    rows.iter()
+        .map(|row| row.iter().map(|x| x.map_or(f64::NAN, |x| x.altitude)).collect())
+        .collect()
+}
+
+/// Ground each pixel covers in square metres, from the distance to its neighbors across and
+/// down the map, so the water it collects doesn't depend on the resolution or the projection
+fn get_pixel_areas(rows: &[&Vec<Option<PixelSample>>]) -> Vec2D<f64> {
+    let get_point = |h: usize, w: usize| rows.get(h)?.get(w).copied().flatten().map(|x| x.point);
+    let get_spacing = |h: usize, w: usize, neighbors: [Option<(usize, usize)>; 2]| {
+        let point = get_point(h, w)?;
+        neighbors.iter().flatten().find_map(|(nh, nw)| {
+            let offset = get_point(*nh, *nw)?.sub(&point);
+            Some((offset.x * offset.x + offset.y * offset.y + offset.z * offset.z).sqrt() * PLANET_RADIUS)
+        })
+    };
+    rows.iter()
+        .enumerate()
+        .map(|(h, row)| {
+            (0..row.len())
+                .map(|w| {
+                    let across = get_spacing(h, w, [Some((h, w + 1)), w.checked_sub(1).map(|x| (h, x))]);
+                    let down = get_spacing(h, w, [Some((h + 1, w)), h.checked_sub(1).map(|x| (x, w))]);
+                    match (across, down) {
+                        (Some(across), Some(down)) => across * down,
+                        // a pixel on a thin strip of map is taken to be square
+                        (Some(x), None) | (None, Some(x)) => x * x,
+                        (None, None) => 0.0,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Rainfall and evaporation from open water of each pixel, in cubic metres a second over the
+/// ground it covers
+fn get_water_balance(rows: &[&Vec<Option<PixelSample>>], options: &RenderOptions) -> (Vec2D<f64>, Vec2D<f64>) {
+    let areas = get_pixel_areas(rows);
+    let climate = rows
+        .iter()
+        .zip(&areas)
+        .map(|(row, areas)| {
+            row.iter()
+                .zip(areas)
+                .map(|(x, area)| {
+                    x.map_or((0.0, 0.0), |x| {
+                        let (sun, rain_y) = get_sunlight(x.point.y, options.obliquity, options.season);
+                        let temp = get_temperature(sun, x.altitude);
+                        // metres a year
+                        let rain = get_millimetres(get_rainfall(temp, rain_y, x.rain_shadow)) / 1000.0;
+                        let evaporation = EVAPORATION + EVAPORATION_PER_DEGREE * get_celsius(temp);
+                        let volume = area / SECONDS_PER_YEAR;
+                        (rain * volume, evaporation.max(0.0) * volume)
+                    })
+                })
+                .collect::<Vec<(f64, f64)>>()
+        })
+        .collect::<Vec2D<(f64, f64)>>();
+    (
+        climate.iter().map(|x| x.iter().map(|x| x.0).collect()).collect(),
+        climate.iter().map(|x| x.iter().map(|x| x.1).collect()).collect(),
+    )
+}
+
+fn get_neighbors(heights: &Vec2D<f64>, h: usize, w: usize) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+    NEIGHBORS.iter().filter_map(move |(dh, dw)| {
+        let (nh, nw) = (h.checked_add_signed(*dh)?, w.checked_add_signed(*dw)?);
+        let height = *heights.get(nh)?.get(nw)?;
+        let distance = if *dh != 0 && *dw != 0 { SQRT_2 } else { 1.0 };
+        (!height.is_nan()).then_some((nh, nw, distance))
+    })
+}
+
+/// Pixel waiting to be flooded, ordered lowest first
+struct Cell {
+    height: f64,
+    h: usize,
+    w: usize,
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.height.total_cmp(&self.height).then((other.h, other.w).cmp(&(self.h, self.w)))
+    }
+}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Cell {}
+
+/// Raises every pixel that cannot drain to the sea or off the map to the level it would spill
+/// over at, by flooding inwards from the outlets lowest first (Barnes et al. 2014). Each raised
+/// pixel is a step above the one it was flooded from.
+fn fill_depressions(heights: &Vec2D<f64>, step: f64) -> Vec2D<f64> {
+    let mut filled = heights.clone();
+    let mut done = heights.iter().map(|x| x.iter().map(|x| x.is_nan()).collect()).collect::<Vec2D<bool>>();
+    let mut queue = BinaryHeap::new();
+
+    for (h, row) in heights.iter().enumerate() {
+        for (w, height) in row.iter().enumerate() {
+            if height.is_nan() {
+                continue;
+            }
+            let on_edge = get_neighbors(heights, h, w).count() < NEIGHBORS.len();
+            if *height <= 0.0 || on_edge {
+                done[h][w] = true;
+                queue.push(Cell { height: *height, h, w });
+            }
+        }
+    }
+
+    while let Some(cell) = queue.pop() {
+        for (nh, nw, _) in get_neighbors(heights, cell.h, cell.w) {
+            if !done[nh][nw] {
+                done[nh][nw] = true;
+                filled[nh][nw] = heights[nh][nw].max(cell.height + step);
+                queue.push(Cell { height: filled[nh][nw], h: nh, w: nw });
+            }
+        }
+    }
+    filled
+}
+
+/// Steepest downhill neighbor of each land pixel on the filled surface. Pixels on the edge of the
+/// map may have none, and drain off it.
+fn get_downstream(filled: &Vec2D<f64>, heights: &Vec2D<f64>) -> Vec2D<Option<(usize, usize)>> {
+    filled
+        .iter()
+        .enumerate()
+        .map(|(h, row)| {
+            (0..row.len())
+                .map(|w| {
+                    if heights[h][w].is_nan() || heights[h][w] <= 0.0 {
+                        return None;
+                    }
+                    get_neighbors(filled, h, w)
+                        .map(|(nh, nw, distance)| ((filled[h][w] - filled[nh][nw]) / distance, nh, nw))
+                        .filter(|x| x.0 > 0.0)
+                        .max_by(|a, b| a.0.total_cmp(&b.0))
+                        .map(|x| (x.1, x.2))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Sums the rainfall of every pixel draining through each pixel, including its own
+fn accumulate_flow(
+    filled: &Vec2D<f64>,
+    downstream: &Vec2D<Option<(usize, usize)>>,
+    rainfall: &Vec2D<f64>,
+) -> Vec2D<f64> {
+    let mut order = filled
+        .iter()
+        .enumerate()
+        .flat_map(|(h, row)| row.iter().enumerate().filter(|x| !x.1.is_nan()).map(move |(w, _)| (h, w)))
+        .collect::<Vec<(usize, usize)>>();
+    order.sort_by(|a, b| filled[b.0][b.1].total_cmp(&filled[a.0][a.1]));
+
+    let mut flow = rainfall.clone();
+    for (h, w) in order {
+        if let Some((nh, nw)) = downstream[h][w] {
+            flow[nh][nw] += flow[h][w];
+        }
+    }
+    flow
+}
+
+/// Groups the pixels raised by filling into basins of the same spill level, leaving out shallow
+/// ones, and finds the level
+/// each fills to: pixels are flooded lowest first while the water draining into the basin can
+/// make up for evaporation from the flooded surface
+fn find_basins(
+    heights: &Vec2D<f64>,
+    spill: &Vec2D<f64>,
+    flow: &Vec2D<f64>,
+    evaporation: &Vec2D<f64>,
+) -> Vec<Basin> {
+    let mut done = vec![vec![false; heights.first().map_or(0, |x| x.len())]; heights.len()];
+    let mut basins = vec![];
+    for (start_h, row) in heights.iter().enumerate() {
+        for (start_w, height) in row.iter().enumerate() {
+            if done[start_h][start_w] || height.is_nan() || spill[start_h][start_w] <= *height {
+                continue;
+            }
+            let level = spill[start_h][start_w];
+            let mut cells = vec![(start_h, start_w)];
+            let mut stack = vec![(start_h, start_w)];
+            done[start_h][start_w] = true;
+            while let Some((h, w)) = stack.pop() {
+                for (nh, nw, _) in get_neighbors(heights, h, w) {
+                    if !done[nh][nw] && spill[nh][nw] == level && heights[nh][nw] < level {
+                        done[nh][nw] = true;
+                        cells.push((nh, nw));
+                        stack.push((nh, nw));
+                    }
+                }
+            }
+
+            cells.sort_by(|a, b| heights[a.0][a.1].total_cmp(&heights[b.0][b.1]));
+            if level - heights[cells[0].0][cells[0].1] < MIN_BASIN_DEPTH {
+                continue;
+            }
+
+            // everything draining into the basin passes through the pixel it leaves by
+            let inflow = cells.iter().map(|(h, w)| flow[*h][*w]).fold(0.0, f64::max);
+            let mut loss = 0.0;
+            let flooded = cells
+                .iter()
+                .take_while(|(h, w)| {
+                    loss += evaporation[*h][*w];
+                    loss <= inflow
+                })
+                .count();
+            basins.push(Basin {
+                level: cells.get(flooded).map_or(level, |(h, w)| heights[*h][*w]),
+                overflows: flooded == cells.len(),
+                cells,
+            });
+        }
+    }
+    basins
+}
+
+/// Splits the river pixels into stretches between sources, confluences and mouths
+fn trace_rivers(
+    downstream: &Vec2D<Option<(usize, usize)>>,
+    flow: &Vec2D<f64>,
+    is_river: &dyn Fn(usize, usize) -> bool,
+    get_location: impl Fn(usize, usize) -> (f64, f64),
+) -> Vec<River> {
+    let mut tributaries = vec![vec![0u8; flow.first().map_or(0, |x| x.len())]; flow.len()];
+    for (h, row) in downstream.iter().enumerate() {
+        for (w, next) in row.iter().enumerate() {
+            if let Some((nh, nw)) = next.filter(|_| is_river(h, w)) {
+                tributaries[nh][nw] += 1;
+            }
+        }
+    }
+
+    let mut rivers = vec![];
+    for (h, row) in tributaries.iter().enumerate() {
+        for (w, count) in row.iter().enumerate() {
+            if !is_river(h, w) || *count == 1 {
+                continue;
+            }
+            let mut points = vec![get_location(h, w)];
+            let mut discharge = flow[h][w];
+            let mut current = (h, w);
+            while let Some((nh, nw)) = downstream[current.0][current.1] {
+                points.push(get_location(nh, nw));
+                if !is_river(nh, nw) || tributaries[nh][nw] > 1 {
+                    break;
+                }
+                discharge = flow[nh][nw];
+                current = (nh, nw);
+            }
+            if points.len() > 1 {
+                rivers.push(River { points, discharge });
+            }
+        }
+    }
+    rivers
+}
+
+#[test]
+fn test_river_network() {
+    // a valley draining south to the sea, with a pit that has to be filled on the way
+    let heights = [
+        "9 9 9 9 9",
+        "9 5 4 5 9",
+        "9 6 3 6 9",
+        "9 7 1 7 9",
+        "9 8 2 8 9",
+        "9 9 1 9 9",
+        "0 0 0 0 0",
+    ]
+    .iter()
+    .map(|x| x.split(' ').map(|x| x.parse::<f64>().unwrap()).collect())
+    .collect::<Vec2D<f64>>();
+
+    let filled = fill_depressions(&heights, FILL_STEP);
+    assert!(filled[3][2] > 2.0 && filled[3][2] < 2.0 + 1e-6);
+    assert_eq!(filled[1][1], 5.0);
+
+    let downstream = get_downstream(&filled, &heights);
+    assert_eq!(downstream[3][2], Some((4, 2)));
+    assert_eq!(downstream[5][2], Some((6, 2)));
+
+    // rain falls on the inner pixels, all of which drain through the valley but the two beside its mouth
+    let rainfall = (0..7)
+        .map(|h| (0..5).map(|w| if (1..6).contains(&h) && (1..4).contains(&w) { 1.0 } else { 0.0 }).collect())
+        .collect::<Vec2D<f64>>();
+    let flow = accumulate_flow(&filled, &downstream, &rainfall);
+    assert_eq!(flow[5][2], 13.0);
+
+    let is_river = |h: usize, w: usize| heights[h][w] > 0.0 && flow[h][w] >= 3.0;
+    let rivers = trace_rivers(&downstream, &flow, &is_river, |h, w| (h as f64, w as f64));
+    assert_eq!(rivers.len(), 1);
+    assert_eq!(rivers[0].points.first(), Some(&(2.0, 2.0)));
+    assert_eq!(rivers[0].points.last(), Some(&(6.0, 2.0)));
+    assert_eq!(rivers[0].discharge, 13.0);
+}
+
+#[test]
+fn test_basins() {
+    // a closed basin of three pixels spilling at 6 to the sea in the south
+    let heights = [
+        "9 9 9 9 9",
+        "9 2 3 4 9",
+        "9 9 9 6 9",
+        "0 0 0 0 0",
+    ]
+    .iter()
+    .map(|x| x.split(' ').map(|x| x.parse::<f64>().unwrap()).collect())
+    .collect::<Vec2D<f64>>();
+    let spill = fill_depressions(&heights, 0.0);
+    assert_eq!(spill[1][1], 6.0);
+    assert_eq!(spill[2][3], 6.0);
+
+    let evaporation = vec![vec![1.0; 5]; 4];
+    let basins_with_inflow = |inflow: f64| {
+        let flow = vec![vec![inflow; 5]; 4];
+        find_basins(&heights, &spill, &flow, &evaporation)
+    };
+
+    let basins = basins_with_inflow(5.0);
+    assert_eq!(basins.len(), 1);
+    assert_eq!(basins[0].cells, [(1, 1), (1, 2), (1, 3)]);
+    assert!(basins[0].overflows);
+    assert_eq!(basins[0].level, 6.0);
+
+    // enough water for two pixels leaves an endorheic lake below the third
+    let basins = basins_with_inflow(2.5);
+    assert!(!basins[0].overflows);
+    assert_eq!(basins[0].level, 4.0);
+
+    // and a desert basin stays dry
+    assert_eq!(basins_with_inflow(0.5)[0].level, 2.0);
+}
@@ -0,0 +1,238 @@
+use crate::render::RenderState;
+use crate::util::Vec2D;
+use std::f64::consts::PI;
+
+/// Position on the unit sphere of a rendered pixel, and whether it is above sea level
+#[derive(Clone, Copy)]
+pub struct SurfacePoint {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub is_land: bool,
+}
+
+impl SurfacePoint {
+    fn distance(&self, other: &SurfacePoint) -> f64 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        ((dx * dx + dy * dy + dz * dz) as f64).sqrt()
+    }
+
+    fn get_latitude(&self) -> f64 {
+        -(self.y as f64).clamp(-1.0, 1.0).asin().to_degrees()
+    }
+
+    fn get_longitude(&self) -> f64 {
+        (self.x as f64).atan2(self.z as f64).to_degrees()
+    }
+}
+
+/// A connected area of land pixels. Latitudes and longitudes are in degrees.
+pub struct Landmass {
+    pub id: u32,
+    pub pixels: usize,
+    /// Fraction of the whole planet's surface
+    pub surface_fraction: f64,
+    pub centroid_latitude: f64,
+    pub centroid_longitude: f64,
+    pub north: f64,
+    pub south: f64,
+    /// Western and eastern edges, with west greater than east if the landmass crosses the antimeridian
+    pub west: f64,
+    pub east: f64,
+    /// Left, top, right and bottom pixels
+    pub pixel_bounds: [usize; 4],
+}
+
+/// Labels the landmasses of a render, numbered from 1 by decreasing area with 0 for water and
+/// pixels outside the map
+pub fn find_landmasses(state: &RenderState) -> (Vec2D<u32>, Vec<Landmass>) {
+    let surface = state.surface.read().unwrap();
+    let rows = surface.iter().flatten().collect::<Vec<&Vec<Option<SurfacePoint>>>>();
+    label_landmasses(&rows, state.options.slicing.width)
+}
+
+fn label_landmasses(rows: &[&Vec<Option<SurfacePoint>>], width: usize) -> (Vec2D<u32>, Vec<Landmass>) {
+    let height = rows.len();
+    let get = |h: usize, w: usize| rows[h][w].filter(|x| x.is_land);
+    let wraps = rows.iter().map(|x| get_wrap(x)).collect::<Vec<Option<(usize, usize)>>>();
+
+    let mut labels = vec![vec![0u32; width]; height];
+    let mut landmasses: Vec<Landmass> = vec![];
+    let mut stack: Vec<(usize, usize)> = vec![];
+
+    for start_h in 0..height {
+        for start_w in 0..width {
+            if labels[start_h][start_w] != 0 || get(start_h, start_w).is_none() {
+                continue;
+            }
+            let id = landmasses.len() as u32 + 1;
+            let mut area = 0.0;
+            let mut center = [0.0; 3];
+            let mut longitudes: Vec<f64> = vec![];
+            let (mut north, mut south) = (f64::MIN, f64::MAX);
+            let mut pixel_bounds = [start_w, start_h, start_w, start_h];
+            let mut pixels = 0;
+
+            labels[start_h][start_w] = id;
+            stack.push((start_h, start_w));
+            while let Some((h, w)) = stack.pop() {
+                let point = rows[h][w].unwrap();
+                let pixel_area = get_pixel_area(rows, h, w);
+                pixels += 1;
+                area += pixel_area;
+                center[0] += pixel_area * point.x as f64;
+                center[1] += pixel_area * point.y as f64;
+                center[2] += pixel_area * point.z as f64;
+                north = north.max(point.get_latitude());
+                south = south.min(point.get_latitude());
+                longitudes.push(point.get_longitude());
+                pixel_bounds = [
+                    pixel_bounds[0].min(w),
+                    pixel_bounds[1].min(h),
+                    pixel_bounds[2].max(w),
+                    pixel_bounds[3].max(h),
+                ];
+
+                let mut neighbors = vec![];
+                if h > 0 {
+                    neighbors.push((h - 1, w));
+                }
+                if h + 1 < height {
+                    neighbors.push((h + 1, w));
+                }
+                if w > 0 {
+                    neighbors.push((h, w - 1));
+                }
+                if w + 1 < width {
+                    neighbors.push((h, w + 1));
+                }
+                match wraps[h] {
+                    Some((left, right)) if w == left => neighbors.push((h, right)),
+                    Some((left, right)) if w == right => neighbors.push((h, left)),
+                    _ => {}
+                }
+                for (nh, nw) in neighbors {
+                    if labels[nh][nw] == 0 && get(nh, nw).is_some() {
+                        labels[nh][nw] = id;
+                        stack.push((nh, nw));
+                    }
+                }
+            }
+
+            let length = (center[0] * center[0] + center[1] * center[1] + center[2] * center[2]).sqrt();
+            let centroid = SurfacePoint {
+                x: (center[0] / length) as f32,
+                y: (center[1] / length) as f32,
+                z: (center[2] / length) as f32,
+                is_land: true,
+            };
+            let (west, east) = get_longitude_bounds(&mut longitudes);
+            landmasses.push(Landmass {
+                id,
+                pixels,
+                surface_fraction: area / (4.0 * PI),
+                centroid_latitude: centroid.get_latitude(),
+                centroid_longitude: centroid.get_longitude(),
+                north,
+                south,
+                west,
+                east,
+                pixel_bounds,
+            });
+        }
+    }
+
+    // renumber by decreasing area
+    landmasses.sort_by(|a, b| b.surface_fraction.total_cmp(&a.surface_fraction));
+    let mut new_ids = vec![0u32; landmasses.len() + 1];
+    for (i, landmass) in landmasses.iter_mut().enumerate() {
+        new_ids[landmass.id as usize] = i as u32 + 1;
+        landmass.id = i as u32 + 1;
+    }
+    for label in labels.iter_mut().flatten() {
+        *label = new_ids[*label as usize];
+    }
+    (labels, landmasses)
+}
+
+/// Returns the first and last pixels of a row if they are neighbors on the sphere, as they are
+/// for projections that show the whole world
+fn get_wrap(row: &[Option<SurfacePoint>]) -> Option<(usize, usize)> {
+    let left = row.iter().position(|x| x.is_some())?;
+    let right = row.iter().rposition(|x| x.is_some())?;
+    let spacing = row.get(left + 1)?.as_ref()?.distance(row[left].as_ref()?);
+    if right > left + 1 && row[left]?.distance(row[right].as_ref()?) < 2.0 * spacing {
+        Some((left, right))
+    } else {
+        None
+    }
+}
+
+/// Approximates the area of a pixel on the unit sphere from the distances to its neighbors
+fn get_pixel_area(rows: &[&Vec<Option<SurfacePoint>>], h: usize, w: usize) -> f64 {
+    let point = rows[h][w].unwrap();
+    let neighbor = |h: Option<usize>, w: Option<usize>| {
+        rows.get(h?)?.get(w?)?.map(|x| (x.x - point.x, x.y - point.y, x.z - point.z))
+    };
+    let across = neighbor(Some(h), w.checked_add(1)).or_else(|| neighbor(Some(h), w.checked_sub(1)));
+    let down = neighbor(h.checked_add(1), Some(w)).or_else(|| neighbor(h.checked_sub(1), Some(w)));
+    match (across, down) {
+        (Some(a), Some(d)) => {
+            let cross = (a.1 * d.2 - a.2 * d.1, a.2 * d.0 - a.0 * d.2, a.0 * d.1 - a.1 * d.0);
+            ((cross.0 * cross.0 + cross.1 * cross.1 + cross.2 * cross.2) as f64).sqrt()
+        }
+        _ => 0.0,
+    }
+}
+
+/// Finds the narrowest range of longitudes containing all of them, by leaving out the widest gap
+fn get_longitude_bounds(longitudes: &mut [f64]) -> (f64, f64) {
+    longitudes.sort_by(f64::total_cmp);
+    let count = longitudes.len();
+    let mut widest = (longitudes[0] + 360.0 - longitudes[count - 1], 0);
+    for i in 1..count {
+        let gap = longitudes[i] - longitudes[i - 1];
+        if gap > widest.0 {
+            widest = (gap, i);
+        }
+    }
+    (longitudes[widest.1], longitudes[(widest.1 + count - 1) % count])
+}
+
+#[test]
+fn test_landmass_labeling() {
+    // whole world grid of 8 columns and 4 rows, centered on longitude 0
+    let grid = |land: &[&str]| {
+        land.iter()
+            .enumerate()
+            .map(|(h, row)| {
+                row.chars()
+                    .enumerate()
+                    .map(|(w, c)| {
+                        let latitude = (67.5 - 45.0 * h as f64).to_radians();
+                        let longitude = (-157.5 + 45.0 * w as f64).to_radians();
+                        Some(SurfacePoint {
+                            x: (longitude.sin() * latitude.cos()) as f32,
+                            y: -latitude.sin() as f32,
+                            z: (longitude.cos() * latitude.cos()) as f32,
+                            is_land: c == '#',
+                        })
+                    })
+                    .collect::<Vec<Option<SurfacePoint>>>()
+            })
+            .collect::<Vec<Vec<Option<SurfacePoint>>>>()
+    };
+
+    let rows = grid(&["........", "#......#", "...##...", "...#...."]);
+    let (labels, landmasses) = label_landmasses(&rows.iter().collect::<Vec<_>>(), 8);
+    assert_eq!(landmasses.len(), 2);
+
+    // the island in the south is larger than the one crossing the antimeridian
+    assert_eq!(landmasses[0].pixels, 3);
+    assert!(landmasses[0].centroid_latitude < 0.0);
+    assert_eq!(landmasses[0].pixel_bounds, [3, 2, 4, 3]);
+    assert_eq!(landmasses[1].pixels, 2);
+    assert!((landmasses[1].west - 157.5).abs() < 1e-3 && (landmasses[1].east + 157.5).abs() < 1e-3);
+    assert_eq!(labels[1], [2, 0, 0, 0, 0, 0, 0, 2]);
+    assert!((landmasses[1].centroid_longitude.abs() - 180.0).abs() < 1e-3);
+}
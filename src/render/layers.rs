@@ -0,0 +1,42 @@
+/// Per-pixel data that can be written to its own file alongside the color image
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layer {
+    Altitude,
+    Temperature,
+    Rainfall,
+    RainShadow,
+    Biome,
+}
+
+pub const LAYER_NAMES: [&str; 5] = ["altitude", "temperature", "rainfall", "rain_shadow", "biome"];
+
+impl Layer {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "altitude" => Some(Layer::Altitude),
+            "temperature" => Some(Layer::Temperature),
+            "rainfall" => Some(Layer::Rainfall),
+            "rain_shadow" => Some(Layer::RainShadow),
+            "biome" => Some(Layer::Biome),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Layer::Altitude => LAYER_NAMES[0],
+            Layer::Temperature => LAYER_NAMES[1],
+            Layer::Rainfall => LAYER_NAMES[2],
+            Layer::RainShadow => LAYER_NAMES[3],
+            Layer::Biome => LAYER_NAMES[4],
+        }
+    }
+}
+
+#[test]
+fn test_layer_names() {
+    for name in LAYER_NAMES {
+        assert_eq!(Layer::from_name(name).unwrap().get_name(), name);
+    }
+    assert!(Layer::from_name("humidity").is_none());
+}
@@ -0,0 +1,155 @@
+use crate::render::altitude::{
+    calc_altitude, create_sampling_state, get_sphere_samples, SPHERE_SAMPLES,
+};
+use crate::render::climate::{
+    get_celsius, get_climate, get_millimetres, get_rainfall, get_sunlight, get_temperature,
+};
+use crate::render::RenderOptions;
+use crate::terrain::koppen;
+use crate::terrain::koppen::KOPPEN_ZONES;
+
+const HISTOGRAM_BINS: usize = 20;
+
+// percentages of the surface the hypsometric curve is given at
+const HYPSOMETRIC_STEP: usize = 5;
+
+pub const STATS_FORMATS: [&str; 2] = ["text", "json"];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+impl StatsFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(StatsFormat::Text),
+            "json" => Some(StatsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Whole planet measurements. Samples are spread evenly over the sphere, so each fraction is a
+/// fraction of surface area rather than of the projected image.
+pub struct PlanetStats {
+    pub samples: usize,
+    pub land_fraction: f64,
+    pub min_altitude: f64,
+    pub max_altitude: f64,
+    pub mean_altitude: f64,
+    /// Lower bound of each equal width altitude bin and the fraction of the surface in it
+    pub histogram: Vec<(f64, f64)>,
+    /// Percentage of the surface and the altitude that percentage lies above
+    pub hypsometric_curve: Vec<(usize, f64)>,
+    /// Degrees Celsius over the whole surface and over land
+    pub mean_temperature: f64,
+    pub mean_land_temperature: f64,
+    /// Millimetres a year over the whole surface and over land
+    pub mean_rainfall: f64,
+    pub mean_land_rainfall: f64,
+    /// Code, name and fraction of land of each biome, or each Köppen zone
+    pub biomes: Vec<(String, String, f64)>,
+}
+
+pub fn get_planet_stats(options: &RenderOptions) -> PlanetStats {
+    // rainfall needs the rain shadow even when the render did not
+    let mut options = options.clone();
+    options.calculate_rain_shadow = true;
+    let mut state = create_sampling_state(&options);
+    let mut altitudes = Vec::with_capacity(SPHERE_SAMPLES);
+    let mut biome_counts = [0usize; 256];
+    let (mut temperature, mut land_temperature) = (0.0, 0.0);
+    let (mut rainfall, mut land_rainfall) = (0.0, 0.0);
+
+    for p in get_sphere_samples(SPHERE_SAMPLES) {
+        let alt = calc_altitude(&mut state, &p);
+        let (sun, rain_y) = get_sunlight(p.y, options.obliquity, options.season);
+        let temp = get_temperature(sun, alt);
+        let rain = get_rainfall(temp, rain_y, state.rain_shadow);
+
+        altitudes.push(alt);
+        temperature += get_celsius(temp);
+        rainfall += get_millimetres(rain);
+        if alt > 0.0 {
+            land_temperature += get_celsius(temp);
+            land_rainfall += get_millimetres(rain);
+            if options.show_koppen {
+                biome_counts[koppen::classify(&get_climate(
+                    &p,
+                    alt,
+                    state.rain_shadow,
+                    options.obliquity,
+                ))] += 1;
+            } else {
+                biome_counts[options.biome_table.get_biome(temp, rain) as usize] += 1;
+            }
+        }
+    }
+
+    let samples = altitudes.len() as f64;
+    let land = altitudes.iter().filter(|x| **x > 0.0).count();
+    let land_samples = land.max(1) as f64;
+    altitudes.sort_by(f64::total_cmp);
+    let min_altitude = altitudes[0];
+    let max_altitude = altitudes[altitudes.len() - 1];
+
+    let bin_width = (max_altitude - min_altitude).max(f64::EPSILON) / HISTOGRAM_BINS as f64;
+    let mut bin_counts = [0usize; HISTOGRAM_BINS];
+    for alt in &altitudes {
+        bin_counts[(((alt - min_altitude) / bin_width) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let histogram = bin_counts
+        .iter()
+        .enumerate()
+        .map(|(i, count)| (min_altitude + i as f64 * bin_width, *count as f64 / samples))
+        .collect();
+
+    let hypsometric_curve = (0..=100)
+        .step_by(HYPSOMETRIC_STEP)
+        .map(|percent| {
+            let index = ((100 - percent) as f64 / 100.0 * samples) as usize;
+            (percent, altitudes[index.min(altitudes.len() - 1)])
+        })
+        .collect();
+
+    let biomes = if options.show_koppen {
+        KOPPEN_ZONES
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                (
+                    x.code.to_string(),
+                    x.name.to_string(),
+                    biome_counts[i] as f64 / land_samples,
+                )
+            })
+            .collect()
+    } else {
+        options
+            .biome_table
+            .types
+            .iter()
+            .map(|x| {
+                let share = biome_counts[x.code as usize] as f64 / land_samples;
+                ((x.code as char).to_string(), x.name.clone(), share)
+            })
+            .collect()
+    };
+
+    PlanetStats {
+        samples: altitudes.len(),
+        land_fraction: land as f64 / samples,
+        min_altitude,
+        max_altitude,
+        mean_altitude: altitudes.iter().sum::<f64>() / samples,
+        histogram,
+        hypsometric_curve,
+        mean_temperature: temperature / samples,
+        mean_land_temperature: land_temperature / land_samples,
+        mean_rainfall: rainfall / samples,
+        mean_land_rainfall: land_rainfall / land_samples,
+        biomes,
+    }
+}
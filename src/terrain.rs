@@ -1,59 +1,252 @@
-use std::borrow::ToOwned;
-use std::sync::LazyLock;
+use crate::color::Color;
+use std::fmt;
+use std::fs;
+
+pub mod koppen;
+
+// Format of a biome file is a list of biome definitions followed by the classification grid.
+// Definitions are lines of the form:
+// code red green blue name
+// where code is a single uppercase letter, 0 <= red, green, blue <= 255,
+// and name is the rest of the line.
+// The grid is a block of lines containing only biome codes, all of the same length.
+// Rows run from driest to wettest and columns from coldest to hottest, stretched over the
+// rainfall and temperature ranges of the default 45x45 grid.
+// Anything after a # is a comment.
 
 // T = tundra, G = grasslands, B = Taiga / boreal forest, D = desert,
 // S = savanna, F = temperate forest, R = temperate rainforest,
 // W = Xeric shrubland and dry forest, E = tropical dry forest,
 // O = tropical rainforest, I = icecap
-pub static biomes: LazyLock<[[u8; 45]; 45]> = LazyLock::new(|| {
-    [
-        b"IIITTTTTGGGGGGGGDDDDDDDDDDDDDDDDDDDDDDDDDDDDD".to_owned(),
-        b"IIITTTTTGGGGGGGGDDDDGGDSDDSDDDDDDDDDDDDDDDDDD".to_owned(),
-        b"IITTTTTTTTTBGGGGGGGGGGGSSSSSSDDDDDDDDDDDDDDDD".to_owned(),
-        b"IITTTTTTTTBBBBBBGGGGGGGSSSSSSSSSWWWWWWWDDDDDD".to_owned(),
-        b"IITTTTTTTTBBBBBBGGGGGGGSSSSSSSSSSWWWWWWWWWWDD".to_owned(),
-        b"IIITTTTTTTBBBBBBFGGGGGGSSSSSSSSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIITTTTTTBBBBBBFFGGGGGSSSSSSSSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIIITTTTTBBBBBBFFFFGGGSSSSSSSSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIIITTTTTBBBBBBBFFFFGGGSSSSSSSSSSSWWWWWWWWWW".to_owned(),
-        b"IIIIIITTTTBBBBBBBFFFFFFGGGSSSSSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIIIIITTTBBBBBBBFFFFFFFFGGGSSSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIIIIIITTBBBBBBBFFFFFFFFFFGGSSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIIIIIIITBBBBBBBFFFFFFFFFFFFFSSSSWWWWWWWWWWW".to_owned(),
-        b"IIIIIIIIIITBBBBBBFFFFFFFFFFFFFFFSSEEEWWWWWWWW".to_owned(),
-        b"IIIIIIIIIITBBBBBBFFFFFFFFFFFFFFFFFFEEEEEEWWWW".to_owned(),
-        b"IIIIIIIIIIIBBBBBBFFFFFFFFFFFFFFFFFFEEEEEEEEWW".to_owned(),
-        b"IIIIIIIIIIIBBBBBBRFFFFFFFFFFFFFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIBBBBBBRFFFFFFFFFFFFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIBBBBBRRRFFFFFFFFFFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIBBBRRRRRFFFFFFFFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIBRRRRRRRFFFFFFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIRRRRRRRRRRFFFFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIRRRRRRRRRRRRFFFFFEEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIRRRRRRRRRRRRRFRREEEEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIRRRRRRRRRRRRRRRREEEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRRRRRRROOEEEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRRRRROOOOOEEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRRROOOOOOEEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRROOOOOOOEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRROOOOOOOEE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRROOOOOOOOE".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRROOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIRROOOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIROOOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIROOOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOO".to_owned(),
-        b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOO".to_owned(),
-    ]
-});
+const DEFAULT_BIOME_GRID: [&[u8; 45]; 45] = [
+    b"IIITTTTTGGGGGGGGDDDDDDDDDDDDDDDDDDDDDDDDDDDDD",
+    b"IIITTTTTGGGGGGGGDDDDGGDSDDSDDDDDDDDDDDDDDDDDD",
+    b"IITTTTTTTTTBGGGGGGGGGGGSSSSSSDDDDDDDDDDDDDDDD",
+    b"IITTTTTTTTBBBBBBGGGGGGGSSSSSSSSSWWWWWWWDDDDDD",
+    b"IITTTTTTTTBBBBBBGGGGGGGSSSSSSSSSSWWWWWWWWWWDD",
+    b"IIITTTTTTTBBBBBBFGGGGGGSSSSSSSSSSSWWWWWWWWWWW",
+    b"IIIITTTTTTBBBBBBFFGGGGGSSSSSSSSSSSWWWWWWWWWWW",
+    b"IIIIITTTTTBBBBBBFFFFGGGSSSSSSSSSSSWWWWWWWWWWW",
+    b"IIIIITTTTTBBBBBBBFFFFGGGSSSSSSSSSSSWWWWWWWWWW",
+    b"IIIIIITTTTBBBBBBBFFFFFFGGGSSSSSSSSWWWWWWWWWWW",
+    b"IIIIIIITTTBBBBBBBFFFFFFFFGGGSSSSSSWWWWWWWWWWW",
+    b"IIIIIIIITTBBBBBBBFFFFFFFFFFGGSSSSSWWWWWWWWWWW",
+    b"IIIIIIIIITBBBBBBBFFFFFFFFFFFFFSSSSWWWWWWWWWWW",
+    b"IIIIIIIIIITBBBBBBFFFFFFFFFFFFFFFSSEEEWWWWWWWW",
+    b"IIIIIIIIIITBBBBBBFFFFFFFFFFFFFFFFFFEEEEEEWWWW",
+    b"IIIIIIIIIIIBBBBBBFFFFFFFFFFFFFFFFFFEEEEEEEEWW",
+    b"IIIIIIIIIIIBBBBBBRFFFFFFFFFFFFFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIBBBBBBRFFFFFFFFFFFFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIIBBBBBRRRFFFFFFFFFFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIIIIBBBRRRRRFFFFFFFFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIIIIIIBRRRRRRRFFFFFFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIIIIIIRRRRRRRRRRFFFFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIIIIIIIRRRRRRRRRRRRFFFFFEEEEEEEEEE",
+    b"IIIIIIIIIIIIIIIIIIIRRRRRRRRRRRRRFRREEEEEEEEEE",
+    b"IIIIIIIIIIIIIIIIIIIIIRRRRRRRRRRRRRRRREEEEEEEE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRRRRRRROOEEEEEE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRRRRROOOOOEEEE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRRROOOOOOEEE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRRROOOOOOOEE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRRROOOOOOOEE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRRRROOOOOOOOE",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIRRRRROOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIRROOOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIROOOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIROOOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOO",
+    b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIOOOOOOO",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiomeType {
+    pub code: u8,
+    pub name: String,
+    pub color: Color,
+}
+
+impl BiomeType {
+    fn new(code: u8, name: &str, color: Color) -> Self {
+        Self {
+            code,
+            name: name.into(),
+            color,
+        }
+    }
+}
+
+/// Classifies biomes by temperature and rainfall
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiomeTable {
+    /// Rows by increasing rainfall, columns by increasing temperature
+    grid: Vec<Vec<u8>>,
+    pub types: Vec<BiomeType>,
+}
+
+impl Default for BiomeTable {
+    fn default() -> Self {
+        Self {
+            grid: DEFAULT_BIOME_GRID.iter().map(|x| x.to_vec()).collect(),
+            types: vec![
+                BiomeType::new(b'T', "Tundra", Color::from_colors(210, 210, 210)),
+                BiomeType::new(b'G', "Grasslands", Color::from_colors(250, 215, 165)),
+                BiomeType::new(b'B', "Taiga / boreal forest", Color::from_colors(105, 155, 120)),
+                BiomeType::new(b'D', "Desert", Color::from_colors(220, 195, 175)),
+                BiomeType::new(b'S', "Savanna", Color::from_colors(225, 155, 100)),
+                BiomeType::new(b'F', "Temperate forest", Color::from_colors(155, 215, 170)),
+                BiomeType::new(b'R', "Temperate rainforest", Color::from_colors(170, 195, 200)),
+                BiomeType::new(b'W', "Xeric shrubland and dry forest", Color::from_colors(185, 150, 160)),
+                BiomeType::new(b'E', "Tropical dry forest", Color::from_colors(130, 190, 25)),
+                BiomeType::new(b'O', "Tropical rainforest", Color::from_colors(110, 160, 170)),
+                BiomeType::new(b'I', "Icecap", Color::from_colors(255, 255, 255)),
+            ],
+        }
+    }
+}
+
+impl BiomeTable {
+    /// Reads a biome table from the specified file
+    pub fn from_file(filename: &str) -> Self {
+        match fs::read_to_string(filename) {
+            Ok(text) => parse_biome_file(&text).unwrap_or_else(|e| {
+                eprintln!("Error parsing biome file: {e}");
+                panic!()
+            }),
+            Err(e) => {
+                eprintln!("Error reading biome file! {:?}", e);
+                panic!()
+            }
+        }
+    }
+
+    /// Colors of each biome, offset from the lowest land color
+    pub fn get_land_colors(&self) -> Vec<(usize, Color)> {
+        self.types
+            .iter()
+            .map(|x| (x.code as usize - 64, x.color.clone()))
+            .collect()
+    }
+
+    /// Looks up the biome code for a temperature and rainfall
+    pub fn get_biome(&self, temp: f64, rain: f64) -> u8 {
+        let rows = self.grid.len();
+        let columns = self.grid[0].len();
+        let tt = ((rain * 300.0 - 9.0) * (rows as f64 / 45.0)) as i32;
+        let rr = ((temp * 300.0 + 10.0) * (columns as f64 / 45.0)) as i32;
+        self.grid[tt.clamp(0, rows as i32 - 1) as usize][rr.clamp(0, columns as i32 - 1) as usize]
+    }
+}
+
+#[derive(Debug)]
+enum BiomeFileParseError {
+    TooFewTokens(usize),
+    InvalidCode(usize),
+    ColorParse(usize),
+    DuplicateCode(char),
+    DefinitionAfterGrid(usize),
+    MissingGrid,
+    UnevenRow(usize),
+    UndefinedCode(char),
+}
+
+impl fmt::Display for BiomeFileParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooFewTokens(line) => write!(f, "line {line}: expected code red green blue name"),
+            Self::InvalidCode(line) => write!(f, "line {line}: biome code must be a letter from A to Z"),
+            Self::ColorParse(line) => write!(f, "line {line}: colors must be from 0 to 255"),
+            Self::DuplicateCode(code) => write!(f, "biome {code} is defined twice"),
+            Self::DefinitionAfterGrid(line) => write!(f, "line {line}: biome defined after the grid"),
+            Self::MissingGrid => write!(f, "no classification grid"),
+            Self::UnevenRow(line) => write!(f, "line {line}: grid rows must all be the same length"),
+            Self::UndefinedCode(code) => write!(f, "grid uses undefined biome {code}"),
+        }
+    }
+}
+
+fn parse_biome_file(text: &str) -> Result<BiomeTable, BiomeFileParseError> {
+    let mut types: Vec<BiomeType> = vec![];
+    let mut grid: Vec<Vec<u8>> = vec![];
+
+    for (i, line) in text.lines().enumerate().map(|(i, x)| (i + 1, x)) {
+        let line = line.split('#').next().unwrap_or("");
+        let tokens = line.split_ascii_whitespace().collect::<Vec<&str>>();
+        match tokens.len() {
+            0 => {}
+            1 => {
+                if grid.first().is_some_and(|x| x.len() != tokens[0].len()) {
+                    return Err(BiomeFileParseError::UnevenRow(i));
+                }
+                grid.push(tokens[0].as_bytes().to_vec());
+            }
+            2..=4 => return Err(BiomeFileParseError::TooFewTokens(i)),
+            _ => {
+                if !grid.is_empty() {
+                    return Err(BiomeFileParseError::DefinitionAfterGrid(i));
+                }
+                let code = match tokens[0].as_bytes() {
+                    [code] if code.is_ascii_uppercase() => *code,
+                    _ => return Err(BiomeFileParseError::InvalidCode(i)),
+                };
+                if types.iter().any(|x| x.code == code) {
+                    return Err(BiomeFileParseError::DuplicateCode(code as char));
+                }
+                let color = |x: &str| x.parse::<u8>().map_err(|_| BiomeFileParseError::ColorParse(i));
+                types.push(BiomeType {
+                    code,
+                    name: tokens[4..].join(" "),
+                    color: Color::from_colors(color(tokens[1])?, color(tokens[2])?, color(tokens[3])?),
+                });
+            }
+        }
+    }
+
+    if grid.is_empty() {
+        return Err(BiomeFileParseError::MissingGrid);
+    }
+    if let Some(code) = grid.iter().flatten().find(|x| !types.iter().any(|t| t.code == **x)) {
+        return Err(BiomeFileParseError::UndefinedCode(*code as char));
+    }
+    Ok(BiomeTable { grid, types })
+}
+
+#[test]
+fn test_default_biome_file() {
+    use std::env;
+    use std::path::Path;
+
+    let filepath = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("src")
+        .join("biome_files")
+        .join("default.bio");
+    let table = BiomeTable::from_file(filepath.to_str().unwrap());
+    assert_eq!(table, BiomeTable::default());
+    assert_eq!(table.get_biome(-1.0, 0.0), b'I');
+    assert_eq!(table.get_biome(1.0, 0.0), b'D');
+}
+
+#[test]
+fn test_biome_file_validation() {
+    let table = parse_biome_file("A 1 2 3 Dry land\nZ 4 5 6 Wet land # comment\n\nAA\nZZ\n").unwrap();
+    assert_eq!(table.types[0].name, "Dry land");
+    assert_eq!(table.types[1].name, "Wet land");
+    assert_eq!(table.get_biome(0.0, 0.0), b'A');
+    assert_eq!(table.get_biome(0.0, 1.0), b'Z');
+
+    assert!(matches!(parse_biome_file("A 1 2 3 Land\nAA\nA\n"), Err(BiomeFileParseError::UnevenRow(3))));
+    assert!(matches!(parse_biome_file("A 1 2 3 Land\nAB\n"), Err(BiomeFileParseError::UndefinedCode('B'))));
+    assert!(matches!(parse_biome_file("a 1 2 3 Land\na\n"), Err(BiomeFileParseError::InvalidCode(1))));
+    assert!(matches!(parse_biome_file("A 1 2 300 Land\nA\n"), Err(BiomeFileParseError::ColorParse(1))));
+    assert!(matches!(parse_biome_file("A 1 2 3 Land\n"), Err(BiomeFileParseError::MissingGrid)));
+}
 
 #[derive(Clone)]
 pub struct LatLong {
@@ -0,0 +1,150 @@
+use crate::color::Color;
+
+/// Seasonal climate of a point, in degrees Celsius and millimetres of rain per month
+pub struct Climate {
+    pub warmest_month: f64,
+    pub coldest_month: f64,
+    pub summer_rain: f64,
+    pub winter_rain: f64,
+}
+
+pub struct KoppenZone {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub color: Color,
+}
+
+const fn zone(code: &'static str, name: &'static str, red: u8, green: u8, blue: u8) -> KoppenZone {
+    KoppenZone {
+        code,
+        name,
+        color: Color { red, green, blue },
+    }
+}
+
+// colors follow Peel, Finlayson & McMahon (2007)
+pub const KOPPEN_ZONES: [KoppenZone; 30] = [
+    zone("Af", "Tropical rainforest", 0, 0, 255),
+    zone("Am", "Tropical monsoon", 0, 120, 255),
+    zone("Aw", "Tropical savanna", 70, 170, 250),
+    zone("BWh", "Hot desert", 255, 0, 0),
+    zone("BWk", "Cold desert", 255, 150, 150),
+    zone("BSh", "Hot semi-arid", 245, 165, 0),
+    zone("BSk", "Cold semi-arid", 255, 220, 100),
+    zone("Csa", "Hot-summer Mediterranean", 255, 255, 0),
+    zone("Csb", "Warm-summer Mediterranean", 200, 200, 0),
+    zone("Csc", "Cold-summer Mediterranean", 150, 150, 0),
+    zone("Cwa", "Monsoon-influenced humid subtropical", 150, 255, 150),
+    zone("Cwb", "Subtropical highland", 100, 200, 100),
+    zone("Cwc", "Cold subtropical highland", 50, 150, 50),
+    zone("Cfa", "Humid subtropical", 200, 255, 80),
+    zone("Cfb", "Temperate oceanic", 100, 255, 80),
+    zone("Cfc", "Subpolar oceanic", 50, 200, 0),
+    zone("Dsa", "Hot-summer Mediterranean continental", 255, 0, 255),
+    zone("Dsb", "Warm-summer Mediterranean continental", 200, 0, 200),
+    zone("Dsc", "Mediterranean subarctic", 150, 50, 150),
+    zone("Dsd", "Extremely cold Mediterranean subarctic", 150, 100, 150),
+    zone("Dwa", "Monsoon-influenced hot-summer continental", 170, 175, 255),
+    zone("Dwb", "Monsoon-influenced warm-summer continental", 90, 120, 220),
+    zone("Dwc", "Monsoon-influenced subarctic", 75, 80, 180),
+    zone("Dwd", "Monsoon-influenced extremely cold subarctic", 50, 0, 135),
+    zone("Dfa", "Hot-summer humid continental", 0, 255, 255),
+    zone("Dfb", "Warm-summer humid continental", 55, 200, 255),
+    zone("Dfc", "Subarctic", 0, 125, 125),
+    zone("Dfd", "Extremely cold subarctic", 0, 70, 95),
+    zone("ET", "Tundra", 178, 178, 178),
+    zone("EF", "Ice cap", 102, 102, 102),
+];
+
+/// Returns the index in KOPPEN_ZONES of a climate, using the rules of Peel et al. (2007)
+pub fn classify(climate: &Climate) -> usize {
+    let warmest = climate.warmest_month;
+    let coldest = climate.coldest_month;
+    let mean_temp = (warmest + coldest) / 2.0;
+    let annual_rain = 6.0 * (climate.summer_rain + climate.winter_rain);
+    let driest_month = climate.summer_rain.min(climate.winter_rain);
+
+    if warmest < 10.0 {
+        return get_index(if warmest > 0.0 { "ET" } else { "EF" });
+    }
+
+    // aridity threshold in mm, lower when the rain falls in the cool season
+    let summer_fraction = 6.0 * climate.summer_rain / annual_rain.max(f64::EPSILON);
+    let threshold = 2.0 * mean_temp
+        + if summer_fraction >= 0.7 {
+            28.0
+        } else if summer_fraction <= 0.3 {
+            0.0
+        } else {
+            14.0
+        };
+    if annual_rain < 10.0 * threshold {
+        let aridity = if annual_rain < 5.0 * threshold { "W" } else { "S" };
+        let heat = if mean_temp >= 18.0 { "h" } else { "k" };
+        return get_index(&format!("B{aridity}{heat}"));
+    }
+
+    if coldest >= 18.0 {
+        return get_index(if driest_month >= 60.0 {
+            "Af"
+        } else if driest_month >= 100.0 - annual_rain / 25.0 {
+            "Am"
+        } else {
+            "Aw"
+        });
+    }
+
+    let group = if coldest > 0.0 { "C" } else { "D" };
+    let precipitation = if climate.summer_rain < 40.0 && climate.summer_rain < climate.winter_rain / 3.0 {
+        "s"
+    } else if climate.winter_rain < climate.summer_rain / 10.0 {
+        "w"
+    } else {
+        "f"
+    };
+    let summer = if warmest >= 22.0 {
+        "a"
+    } else if get_warm_months(warmest, coldest) >= 4 {
+        "b"
+    } else if group == "D" && coldest < -38.0 {
+        "d"
+    } else {
+        "c"
+    };
+    get_index(&format!("{group}{precipitation}{summer}"))
+}
+
+/// Counts the months above 10°C, assuming temperature follows a sine wave through the year
+fn get_warm_months(warmest: f64, coldest: f64) -> usize {
+    let mean = (warmest + coldest) / 2.0;
+    let amplitude = (warmest - coldest) / 2.0;
+    (0..12)
+        .filter(|m| mean + amplitude * (std::f64::consts::PI * (*m as f64 + 0.5) / 6.0).cos() > 10.0)
+        .count()
+}
+
+fn get_index(code: &str) -> usize {
+    KOPPEN_ZONES.iter().position(|x| x.code == code).unwrap()
+}
+
+#[test]
+fn test_koppen_classification() {
+    let classify_code = |warmest, coldest, summer_rain, winter_rain| {
+        KOPPEN_ZONES[classify(&Climate {
+            warmest_month: warmest,
+            coldest_month: coldest,
+            summer_rain,
+            winter_rain,
+        })]
+        .code
+    };
+
+    assert_eq!(classify_code(28.0, 26.0, 250.0, 150.0), "Af");
+    assert_eq!(classify_code(29.0, 25.0, 200.0, 10.0), "Aw");
+    assert_eq!(classify_code(35.0, 15.0, 5.0, 5.0), "BWh");
+    assert_eq!(classify_code(24.0, 10.0, 5.0, 80.0), "Csa");
+    assert_eq!(classify_code(17.0, 3.0, 70.0, 80.0), "Cfb");
+    assert_eq!(classify_code(14.0, -20.0, 60.0, 30.0), "Dfc");
+    assert_eq!(classify_code(5.0, -30.0, 20.0, 10.0), "ET");
+    assert_eq!(classify_code(-5.0, -40.0, 20.0, 10.0), "EF");
+}